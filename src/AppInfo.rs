@@ -1,6 +1,23 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
 
-use tui::widgets::ListState;
+use tui::widgets::{ListState, TableState};
+
+use crate::config::Config;
+use crate::filesystem::bookmarks::Bookmark;
+use crate::filesystem::frecency::Frecency;
+use crate::filesystem::conflict::{PendingOperation, Resolution};
+use crate::filesystem::grep::GrepMatch;
+use crate::filesystem::index::{Index, Match};
+use crate::filesystem::listing::{self, Entry, Sort};
+use crate::filesystem::permissions::{self, PermissionChange};
+use crate::filesystem::rename::{RenamePattern, RenamePlan};
+use crate::filesystem::trash::TrashEntry;
+use crate::filesystem::util;
+use crate::jobs::{JobProgress, JobQueue};
+use crate::keymap::{Key, Keymap};
 
 #[derive(PartialEq)]
 pub enum InputMode {
@@ -12,16 +29,210 @@ pub enum InputMode {
 pub enum InputType{
     None,
     Searching,
+    Grep,
     MakeFile,
+    MakeSymlink,
+    MakeHardLink,
     RenameFile,
+    DeletePermanently,
+    EmptyTrash,
+    SelectGlob,
+    ConflictRename,
+    Command,
+    SetBookmark,
+    JumpToBookmark,
+    RenameBookmark,
+    Jump,
 }
-/// App holds the state of the application
 
+/// The field of the pattern rename dialog that is being typed into
+#[derive(PartialEq)]
+pub enum PatternField {
+    Find,
+    Replace,
+}
+
+/// A content search, which runs as a job and fills `results` while it goes
+#[derive(Default)]
+pub struct ContentSearch {
+    pub pattern: String,
+    pub root: String,
+    pub regex: bool,
+    pub results: Arc<Mutex<Vec<GrepMatch>>>,
+    pub job: Option<usize>,
+    pub error: Option<String>,
+}
+
+/// Folders the listing has been in, for going back and forward
+#[derive(Default)]
+pub struct History {
+    pub back: Vec<String>,
+    pub forward: Vec<String>,
+    // The entry the cursor was last on in each folder
+    pub selections: HashMap<String, String>,
+    // How the folder now shown was reached, applied to the stacks once it has been read
+    pub pending: Option<HistoryStep>,
+}
+
+#[derive(Clone, Copy)]
+pub enum HistoryStep {
+    Visit,
+    Back,
+    Forward,
+}
+
+// How many folders back the history goes
+const MAX_HISTORY: usize = 100;
+
+/// The preview of the selected file, kept until the file, its size or mtime, or the number of lines shown changes
+pub struct PreviewCache {
+    pub key: (String, usize, Option<SystemTime>, u64),
+    pub text: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ClipboardMode {
+    Copy,
+    Cut,
+}
+
+/// Paths waiting to be pasted, and whether they were copied or cut
+pub struct Clipboard {
+    pub paths: Vec<String>,
+    pub mode: ClipboardMode,
+}
+
+impl Clipboard {
+    pub fn set(&mut self, paths: Vec<String>, mode: ClipboardMode) {
+        self.paths = paths;
+        self.mode = mode;
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
+
+impl Default for Clipboard {
+    fn default() -> Clipboard {
+        Clipboard {
+            paths: Vec::new(),
+            mode: ClipboardMode::Copy,
+        }
+    }
+}
+
+/// Copies, moves and renames waiting to run, paused whenever one needs a conflict resolved
+#[derive(Default)]
+pub struct OperationQueue {
+    pub pending: VecDeque<PendingOperation>,
+    pub ready: Vec<PendingOperation>,
+    pub apply_to_all: bool,
+    pub resolution: Option<Resolution>,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl OperationQueue {
+    /// The destinations of the operations already cleared to run, which later ones in the batch mustn't reuse
+    pub fn planned(&self) -> Vec<PathBuf> {
+        self.ready.iter().map(|operation| operation.destination.clone()).collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PermissionsFocus {
+    Checkboxes,
+    Octal,
+    Owner,
+}
+
+/// The chmod/chown dialog. One mode is edited and applied to every target.
+pub struct PermissionsDialog {
+    pub targets: Vec<String>,
+    pub mode: u32,
+    pub octal: String,
+    // Left empty to keep each entry's owner
+    pub owner: String,
+    pub current_owner: String,
+    pub focus: PermissionsFocus,
+    // Cell in the checkbox grid, row by row: user, group, other, then setuid/setgid/sticky
+    pub cursor: usize,
+    pub recursive: bool,
+    pub mode_changed: bool,
+}
+
+impl PermissionsDialog {
+    pub fn new(targets: Vec<String>, mode: u32, current_owner: String) -> PermissionsDialog {
+        PermissionsDialog {
+            targets,
+            mode,
+            octal: format!("{:04o}", mode),
+            owner: String::new(),
+            current_owner,
+            focus: PermissionsFocus::Checkboxes,
+            cursor: 0,
+            recursive: false,
+            mode_changed: false,
+        }
+    }
+
+    /// Returns the mode bit behind a checkbox
+    pub fn bit(cell: usize) -> u32 {
+        match cell {
+            9 => 0o4000,
+            10 => 0o2000,
+            11 => 0o1000,
+            _ => 1 << (8 - cell),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.mode ^= PermissionsDialog::bit(self.cursor);
+        self.octal = format!("{:04o}", self.mode);
+        self.mode_changed = true;
+    }
+
+    pub fn edit_octal(&mut self, octal: String) {
+        if let Some(mode) = permissions::parse_octal(&octal) {
+            self.mode = mode;
+            self.mode_changed = true;
+        }
+        self.octal = octal;
+    }
+
+    pub fn change(&self) -> Result<PermissionChange, std::io::Error> {
+        if self.mode_changed && permissions::parse_octal(&self.octal).is_none() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} is not an octal mode", self.octal)));
+        }
+        let (uid, gid) = permissions::parse_owner(&self.owner)?;
+
+        Ok(PermissionChange {
+            mode: if self.mode_changed { Some(self.mode) } else { None },
+            uid,
+            gid,
+        })
+    }
+}
+
+#[derive(PartialEq)]
 pub enum MenuItem{
     Home,
     Text,
     Search,
     MakeFile,
+    Trash,
+    Conflict,
+    Jobs,
+    RenamePreview,
+    PatternRename,
+    Permissions,
+    Palette,
+    Bookmarks,
 } 
 
 impl From<MenuItem> for usize{
@@ -31,21 +242,67 @@ impl From<MenuItem> for usize{
             MenuItem::Text => 1,
             MenuItem::Search => 2,
             MenuItem::MakeFile => 3,
+            MenuItem::Trash => 4,
+            MenuItem::Conflict => 5,
+            MenuItem::Jobs => 6,
+            MenuItem::RenamePreview => 7,
+            MenuItem::PatternRename => 8,
+            MenuItem::Permissions => 9,
+            MenuItem::Palette => 10,
+            MenuItem::Bookmarks => 11,
         }
     }
 }
 
+/// App holds the state of the application
 pub struct App {
     pub input: String,
     pub message: String,
+    pub status: String,
     pub selected_file: String,
     pub current_directory: String,
+    pub history: History,
+    pub frecency: Frecency,
     pub input_type: InputType,
     pub input_mode: InputMode,
     pub active_menu_item: MenuItem,
-    pub directory_list_state: ListState,
+    pub directory_list_state: TableState,
+    // The current directory's entries, sorted, reloaded when the watcher sees a change
+    pub entries: Vec<Entry>,
+    pub sort: Sort,
     pub search_list_state: ListState,
-    pub loaded_files: HashMap<String, String>,
+    pub palette_list_state: ListState,
+    // Filled in by the search running in the background, which is redone on every keystroke
+    pub search_results: Arc<Mutex<Vec<Match>>>,
+    pub search_progress: Option<Arc<JobProgress>>,
+    pub search_error: Option<String>,
+    pub grep: ContentSearch,
+    pub preview: Option<PreviewCache>,
+    pub trash_list_state: ListState,
+    pub trash_entries: Vec<TrashEntry>,
+    pub bookmarks: Vec<Bookmark>,
+    pub bookmarks_list_state: ListState,
+    pub clipboard: Clipboard,
+    pub marked: BTreeSet<String>,
+    pub rename_queue: Vec<String>,
+    pub rename_plan: Option<RenamePlan>,
+    pub rename_list_state: ListState,
+    pub rename_pattern: RenamePattern,
+    pub pattern_field: PatternField,
+    pub permissions: Option<PermissionsDialog>,
+    pub operations: OperationQueue,
+    pub jobs: JobQueue,
+    pub jobs_list_state: ListState,
+    pub quit_pending: bool,
+    pub index: Arc<RwLock<Index>>,
+    pub config: Config,
+    pub keymap: Keymap,
+    // Keys typed so far towards a multi-key binding like `g g`
+    pub pending_keys: Vec<Key>,
+    // Kept so the config can be reloaded with the same start directory
+    pub config_path: PathBuf,
+    pub config_required: bool,
+    pub start_directory: String,
 }
 
 impl Default for App {
@@ -53,14 +310,152 @@ impl Default for App {
         App {
             input: String::new(),
             message: String::new(),
+            status: String::new(),
             selected_file: String::new(),
             current_directory: String::new(),
+            history: History::default(),
+            frecency: Frecency::default(),
             input_type: InputType::None,
             input_mode: InputMode::Normal,
             active_menu_item: MenuItem::Home,
-            directory_list_state: ListState::default(),
+            directory_list_state: TableState::default(),
+            entries: Vec::new(),
+            sort: Sort::default(),
             search_list_state: ListState::default(),
-            loaded_files: HashMap::new(),
+            palette_list_state: ListState::default(),
+            search_results: Arc::new(Mutex::new(Vec::new())),
+            search_progress: None,
+            search_error: None,
+            grep: ContentSearch::default(),
+            preview: None,
+            trash_list_state: ListState::default(),
+            trash_entries: Vec::new(),
+            bookmarks: Vec::new(),
+            bookmarks_list_state: ListState::default(),
+            clipboard: Clipboard::default(),
+            marked: BTreeSet::new(),
+            rename_queue: Vec::new(),
+            rename_plan: None,
+            rename_list_state: ListState::default(),
+            rename_pattern: RenamePattern::default(),
+            pattern_field: PatternField::Find,
+            permissions: None,
+            operations: OperationQueue::default(),
+            jobs: JobQueue::default(),
+            jobs_list_state: ListState::default(),
+            quit_pending: false,
+            index: Arc::new(RwLock::new(Index::default())),
+            config: Config::default(),
+            keymap: Keymap::default(),
+            pending_keys: Vec::new(),
+            config_path: PathBuf::new(),
+            config_required: false,
+            start_directory: String::new(),
         }
     }
-}
\ No newline at end of file
+}
+
+impl App {
+    /// Returns the marked entries, or the entry under the cursor when nothing is marked
+    pub fn targets(&self) -> Vec<String> {
+        if !self.marked.is_empty() {
+            return self.marked.iter().cloned().collect();
+        }
+
+        if self.selected_file.is_empty() {
+            return Vec::new();
+        }
+
+        vec![self.selected_file.clone()]
+    }
+
+    /// Goes to `directory` if it's a folder. The current one is remembered for going back once the new one has been read
+    pub fn change_directory(&mut self, directory: &str) {
+        if directory == self.current_directory || !Path::new(directory).is_dir() {
+            return;
+        }
+
+        self.history.pending = Some(HistoryStep::Visit);
+        util::update_current_directory(directory, &mut self.current_directory);
+    }
+
+    /// Updates the back and forward stacks for the folder just left, now that the new one could be read
+    pub fn record_history(&mut self, previous: &str) {
+        let history = &mut self.history;
+
+        match history.pending.take() {
+            None => {},
+            Some(HistoryStep::Visit) => {
+                history.back.push(previous.to_string());
+                if history.back.len() > MAX_HISTORY {
+                    history.back.remove(0);
+                }
+                history.forward.clear();
+            },
+            Some(HistoryStep::Back) => {
+                history.back.pop();
+                history.forward.push(previous.to_string());
+            },
+            Some(HistoryStep::Forward) => {
+                history.forward.pop();
+                history.back.push(previous.to_string());
+            },
+        }
+    }
+
+    /// Goes back a folder in the history, or forward when `forward` is set. Folders that are gone are dropped.
+    /// The stacks only change once the folder has been read, in record_history. Returns false when there's nowhere to go
+    pub fn step_history(&mut self, forward: bool) -> bool {
+        let (from, step) = match forward {
+            true => (&mut self.history.forward, HistoryStep::Forward),
+            false => (&mut self.history.back, HistoryStep::Back),
+        };
+
+        while let Some(directory) = from.last() {
+            if Path::new(directory).is_dir() {
+                self.current_directory = directory.clone();
+                self.history.pending = Some(step);
+                return true;
+            }
+            from.pop();
+        }
+
+        false
+    }
+
+    pub fn entry_paths(&self) -> Vec<String> {
+        self.entries.iter().map(|entry| entry.path.clone()).collect()
+    }
+
+    /// Re-reads the current directory, keeping the cursor on the same entry if it's still there
+    pub fn reload_entries(&mut self) -> Result<(), std::io::Error> {
+        let show_hidden = self.config.show_hidden;
+        let res = listing::read_entries(&self.current_directory).map(|entries| {
+            self.entries = entries.into_iter().filter(|entry| show_hidden || !entry.name.starts_with('.')).collect();
+        });
+
+        if res.is_err() {
+            self.entries.clear();
+        }
+        self.sort_entries();
+
+        res
+    }
+
+    pub fn sort_entries(&mut self) {
+        listing::sort_entries(&mut self.entries, self.sort);
+
+        let index = self.entries
+            .iter()
+            .position(|entry| entry.path == self.selected_file)
+            .or(self.directory_list_state.selected())
+            .unwrap_or(0);
+        self.directory_list_state.select(Some(index.min(self.entries.len().saturating_sub(1))));
+    }
+
+    pub fn toggle_mark(&mut self, path: &str) {
+        if !self.marked.remove(path) {
+            self.marked.insert(path.to_string());
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::Path;
+
+use crate::config::Config;
+use crate::filesystem::conflict::{self, Operation, PendingOperation, Resolution};
+use crate::filesystem::util;
+use crate::jobs::JobProgress;
+
+pub const USAGE: &str = "usage: RustFileExplorerCLI [--config <file>] [folder | copy|move [--on-conflict overwrite|skip|keep-both|fail] <source>... <destination folder>]";
+
+/// A copy or move run straight from the command line, without the explorer UI
+pub struct Batch {
+    pub operation: Operation,
+    pub on_conflict: Option<Resolution>,
+    pub sources: Vec<String>,
+    pub destination: String,
+}
+
+/// Returns the batch to run, or `None` when the explorer should start normally
+pub fn parse_batch(args: &[String]) -> Result<Option<Batch>, String> {
+    let operation = match args.first().map(|arg| arg.as_str()) {
+        Some("copy") => Operation::Copy,
+        Some("move") => Operation::Move,
+        _ => return Ok(None),
+    };
+
+    let mut on_conflict = None;
+    let mut paths = Vec::new();
+    let mut rest = args[1..].iter();
+
+    while let Some(arg) = rest.next() {
+        let value = match arg.strip_prefix("--on-conflict") {
+            Some("") => rest.next().cloned().ok_or("--on-conflict needs a value")?,
+            Some(value) if value.starts_with('=') => value[1..].to_string(),
+            _ => {
+                paths.push(arg.clone());
+                continue;
+            },
+        };
+
+        on_conflict = match value.as_str() {
+            "fail" => None,
+            _ => Some(Resolution::parse(&value).ok_or(format!("unknown conflict policy: {}", value))?),
+        };
+    }
+
+    let destination = match paths.pop() {
+        Some(data) if !paths.is_empty() => data,
+        _ => return Err("expected at least one source and a destination folder".to_string()),
+    };
+
+    Ok(Some(Batch {
+        operation,
+        on_conflict,
+        sources: paths,
+        destination,
+    }))
+}
+
+/// Takes `--config <path>` out of the arguments, returning the path if it was given
+pub fn take_config(args: &mut Vec<String>) -> Result<Option<String>, String> {
+    let position = match args.iter().position(|arg| arg == "--config" || arg.starts_with("--config=")) {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+
+    let arg = args.remove(position);
+    match arg.strip_prefix("--config=") {
+        Some(path) => Ok(Some(path.to_string())),
+        None if position < args.len() => Ok(Some(args.remove(position))),
+        None => Err("--config needs a path".to_string()),
+    }
+}
+
+/// Returns the folder to open, from the command line, then the config, then where the explorer was started
+pub fn start_directory(args: &[String], config: &Config) -> Result<String, String> {
+    let directory = match args {
+        [] => match &config.start_directory {
+            Some(directory) => util::expand_home(directory),
+            None => return std::env::current_dir().map(|dir| dir.to_string_lossy().into_owned()).map_err(|e| e.to_string()),
+        },
+        [directory] if !directory.starts_with('-') => directory.clone(),
+        _ => return Err(format!("unexpected arguments: {}", args.join(" "))),
+    };
+
+    match std::fs::canonicalize(&directory) {
+        Ok(path) if path.is_dir() => Ok(path.to_string_lossy().into_owned()),
+        _ => Err(format!("not a folder: {}", directory)),
+    }
+}
+
+/// Runs a batch, printing one line per entry. Returns false if anything failed.
+pub fn run_batch(batch: Batch) -> bool {
+    let mut succeeded = true;
+
+    for source in batch.sources {
+        let mut operation = PendingOperation {
+            operation: batch.operation,
+            destination: Path::new(&batch.destination).join(util::strip_directory(&source)),
+            source,
+            overwrite: false,
+        };
+
+        // Each entry runs before the next is checked, so only what's on disk can conflict
+        if conflict::has_conflict(&operation, &[]) {
+            let resolution = match conflict::automatic_resolution(&operation).or(batch.on_conflict.clone()) {
+                Some(data) => data,
+                None => {
+                    eprintln!("{} already exists", operation.destination.display());
+                    succeeded = false;
+                    continue;
+                },
+            };
+
+            match conflict::resolve(&mut operation, &resolution, &[]) {
+                Ok(true) => {},
+                Ok(false) => {
+                    println!("skipped {}", operation.source);
+                    continue;
+                },
+                Err(e) => {
+                    eprintln!("{}: {}", operation.source, e);
+                    succeeded = false;
+                    continue;
+                },
+            }
+        }
+
+        let progress = JobProgress::default();
+        match conflict::run(&operation, &progress) {
+            Ok(_) if progress.skipped() > 0 => println!(
+                "{} -> {}, skipped {} item(s) that can't be copied",
+                operation.source,
+                operation.destination.display(),
+                progress.skipped(),
+            ),
+            Ok(_) => println!("{} -> {}", operation.source, operation.destination.display()),
+            Err(e) => {
+                eprintln!("{}: {}", operation.source, e);
+                succeeded = false;
+            },
+        }
+    }
+
+    succeeded
+}
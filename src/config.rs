@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer, Serialize};
+use tui::style::Color;
+
+use crate::filesystem::listing::Sort;
+use crate::filesystem::util;
+use crate::keymap::{Action, KeySequence, Keymap, Preset};
+use crate::Error;
+
+/// Settings read from `config.toml`. Anything left out keeps its default.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Where the explorer opens when no folder is given on the command line. Defaults to the folder it was started from
+    pub start_directory: Option<String>,
+    /// Milliseconds between redraws when nothing else happens, which is how often job progress updates
+    pub tick_rate: u64,
+    pub sort: Sort,
+    pub show_hidden: bool,
+    pub keymap: KeymapConfig,
+    pub colors: Colors,
+    /// Programs to open files with by extension, instead of the system default
+    pub openers: HashMap<String, Opener>,
+    pub preview: PreviewConfig,
+    pub index: IndexConfig,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            start_directory: None,
+            tick_rate: 200,
+            sort: Sort::default(),
+            show_hidden: true,
+            keymap: KeymapConfig::default(),
+            colors: Colors::default(),
+            openers: HashMap::new(),
+            preview: PreviewConfig::default(),
+            index: IndexConfig::default(),
+        }
+    }
+}
+
+/// The keys used in the listing. The trash, bookmarks and jobs views, search, the palette and the rename, permissions and
+/// conflict dialogs keep their own fixed keys, listed in the bottom bar while they're open, and move with the arrow keys.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct KeymapConfig {
+    pub preset: Preset,
+    /// Bindings applied over the preset, e.g. `"g h" = "parent"`
+    pub bindings: HashMap<KeySequence, Action>,
+}
+
+impl KeymapConfig {
+    pub fn keymap(&self) -> Keymap {
+        Keymap::new(self.preset, &self.bindings)
+    }
+}
+
+/// The colors that set parts of the UI apart. Each is a name like `light_green`, a hex code like `#ffaa00`, or a 256 color index.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Colors {
+    #[serde(deserialize_with = "color")]
+    pub selection: Color,
+    #[serde(deserialize_with = "color")]
+    pub directory: Color,
+    #[serde(deserialize_with = "color")]
+    pub marked: Color,
+    /// Headers and key hints
+    #[serde(deserialize_with = "color")]
+    pub accent: Color,
+    /// Matched characters in search results
+    #[serde(deserialize_with = "color")]
+    pub matched: Color,
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            selection: Color::Yellow,
+            directory: Color::LightBlue,
+            marked: Color::LightMagenta,
+            accent: Color::LightGreen,
+            matched: Color::Yellow,
+        }
+    }
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_color(&text).ok_or_else(|| serde::de::Error::custom(format!("unknown color: {}", text)))
+}
+
+pub fn parse_color(text: &str) -> Option<Color> {
+    if let Some(hex) = text.strip_prefix('#').filter(|hex| hex.len() == 6) {
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    if let Ok(index) = text.parse() {
+        return Some(Color::Indexed(index));
+    }
+
+    let color = match text.to_lowercase().replace(['_', '-', ' '], "").as_str() {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+
+    Some(color)
+}
+
+/// A program to open files with, either just the command or a table that says it runs in the terminal.
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Opener {
+    Command(String),
+    Program {
+        command: String,
+        /// Hands the terminal over until it exits, for programs like editors
+        #[serde(default)]
+        terminal: bool,
+    },
+}
+
+impl Opener {
+    pub fn command(&self) -> &str {
+        match self {
+            Opener::Command(command) | Opener::Program { command, .. } => command,
+        }
+    }
+
+    pub fn terminal(&self) -> bool {
+        matches!(self, Opener::Program { terminal: true, .. })
+    }
+}
+
+/// The panel to the right of the listing
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct PreviewConfig {
+    pub enabled: bool,
+    /// Lines of a text file to show under its details, 0 for none
+    pub lines: usize,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> PreviewConfig {
+        PreviewConfig {
+            enabled: true,
+            lines: 20,
+        }
+    }
+}
+
+/// What the file index covers. The index is rebuilt from scratch whenever this changes.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct IndexConfig {
+    /// Folders to index. Empty means the start directory, and `~` is expanded once loaded
+    pub roots: Vec<String>,
+    /// Globs matched against each entry's name and full path. Matching folders aren't descended into
+    pub exclude: Vec<String>,
+    /// How many levels below a root to go, unlimited when unset
+    pub max_depth: Option<usize>,
+    pub hidden: bool,
+    pub follow_symlinks: bool,
+}
+
+impl Default for IndexConfig {
+    fn default() -> IndexConfig {
+        IndexConfig {
+            roots: Vec::new(),
+            exclude: vec!["node_modules".to_string(), "target".to_string(), ".git".to_string()],
+            max_depth: None,
+            hidden: false,
+            follow_symlinks: false,
+        }
+    }
+}
+
+impl Config {
+    /// Fills in what depends on where the explorer was started.
+    pub fn resolve(&mut self, start_directory: &str) {
+        if self.index.roots.is_empty() {
+            self.index.roots.push(start_directory.to_string());
+        }
+        for root in self.index.roots.iter_mut() {
+            *root = util::expand_home(root);
+        }
+    }
+}
+
+/// Returns where the config is found when `--config` isn't given, `$XDG_CONFIG_HOME/rfe/config.toml`.
+pub fn default_path() -> PathBuf {
+    util::xdg_config_home().join("rfe").join("config.toml")
+}
+
+/// Reads the config at `path`. When it wasn't `required`, as with the default path, a missing file means the defaults.
+pub fn load(path: &Path, required: bool) -> Result<Config, Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound && !required => return Ok(Config::default()),
+        Err(e) => return Err(Error::ReadConfigError(e)),
+    };
+
+    Ok(toml::from_str(&contents)?)
+}
@@ -1,12 +1,20 @@
 use tui::{
-    backend::{Backend, CrosstermBackend}, layout::{Alignment, Constraint, Direction, Layout, Margin}, style::{Color, Modifier, Style}, symbols::line::VERTICAL, text::{Span, Spans}, widgets::{
-        Block, BorderType, Borders, Cell, List, ListItem, ListState, Paragraph, Row, Table, Tabs,
-    }, Terminal,Frame};
-use std::{fs::{metadata, File}, path::Path};
+    backend::Backend, layout::{Alignment, Constraint, Direction, Layout}, style::{Color, Modifier, Style}, text::{Span, Spans}, widgets::{
+        Block, BorderType, Borders, Cell, Clear, List, ListItem, Paragraph, Row, Table,
+    }, layout::Rect, Frame};
+use std::fs::metadata;
 use std::fs;
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 use crate::AppInfo;
 use crate::filesystem;
+use crate::jobs::{Job, JobState};
+use crate::keymap::{Action, Key};
+use crate::palette;
 
 pub fn draw_ui<B: Backend>(f: &mut Frame<B>, app: &mut AppInfo::App){
     let size = f.size();
@@ -17,7 +25,7 @@ pub fn draw_ui<B: Backend>(f: &mut Frame<B>, app: &mut AppInfo::App){
                     [
                         Constraint::Length(2),
                         Constraint::Min(2),
-                        Constraint::Length(2),
+                        Constraint::Length(3),
                     ]
                     .as_ref(),
                 ).split(size);
@@ -30,76 +38,194 @@ pub fn draw_ui<B: Backend>(f: &mut Frame<B>, app: &mut AppInfo::App){
             //Main Content
             
             match app.active_menu_item {
-                AppInfo::MenuItem::Home => {
-                    let file_chunks = Layout::default()
-                    .direction(Direction::Horizontal)
-                    .constraints([
-                        Constraint::Percentage(60),
-                        Constraint::Percentage(40),
-                        ].as_ref(),
-                    ).split(chunks[1]);
+                AppInfo::MenuItem::Home | AppInfo::MenuItem::Conflict | AppInfo::MenuItem::Permissions | AppInfo::MenuItem::Palette => {
+                    let file_chunks = preview_split(app, chunks[1]);
                     
                     let (left, right) = render_file_widget(app);
                     
                     f.render_stateful_widget(left, file_chunks[0], &mut app.directory_list_state);
-                    f.render_widget(right, file_chunks[1]);
+                    if let Some(area) = file_chunks.get(1) {
+                        render_preview(f, app, right, *area);
+                    }
+
+                    if app.active_menu_item == AppInfo::MenuItem::Conflict {
+                        let area = centered_rect(70, 11, chunks[1]);
+                        f.render_widget(Clear, area);
+                        f.render_widget(render_conflict_dialog(app), area);
+                    }
+
+                    if let Some(dialog) = &app.permissions {
+                        let area = centered_rect(60, 16, chunks[1]);
+                        f.render_widget(Clear, area);
+                        f.render_widget(render_permissions_dialog(dialog), area);
+                    }
+
+                    if app.active_menu_item == AppInfo::MenuItem::Palette {
+                        let area = centered_rect(60, 18, chunks[1]);
+                        let palette_chunks = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([
+                            Constraint::Length(3),
+                            Constraint::Min(2),
+                            ].as_ref(),
+                        ).split(area);
+
+                        f.render_widget(Clear, area);
+                        f.render_widget(render_search_bar(app), palette_chunks[0]);
+                        f.render_stateful_widget(render_palette(app), palette_chunks[1], &mut app.palette_list_state);
+                    }
                 },
                 AppInfo::MenuItem::Text => {
-                    let top = render_search_bar(&app);
+                    let top = render_search_bar(app);
                     f.render_widget(top, chunks[1]);
 
                 },
                 AppInfo::MenuItem::Search => {
-                    let file_chunks = Layout::default()
-                    .direction(Direction::Horizontal)
+                    let search_chunks = Layout::default()
+                    .direction(Direction::Vertical)
                     .constraints([
-                        Constraint::Percentage(60),
-                        Constraint::Percentage(40),
+                        Constraint::Length(3),
+                        Constraint::Min(2),
                         ].as_ref(),
                     ).split(chunks[1]);
+
+                    f.render_widget(render_search_bar(app), search_chunks[0]);
+
+                    let file_chunks = preview_split(app, search_chunks[1]);
                     
                     let (left, right) = render_search_results_widget(app);
                     
-                    f.render_stateful_widget(left, file_chunks[0], &mut app.directory_list_state);
-                    f.render_widget(right, file_chunks[1]);
+                    f.render_stateful_widget(left, file_chunks[0], &mut app.search_list_state);
+                    if let Some(area) = file_chunks.get(1) {
+                        f.render_widget(right, *area);
+                    }
                 },
                 AppInfo::MenuItem::MakeFile => {
 
+                },
+                AppInfo::MenuItem::Trash => {
+                    let trash = render_trash(app);
+                    f.render_stateful_widget(trash, chunks[1], &mut app.trash_list_state);
+                },
+                AppInfo::MenuItem::Bookmarks => {
+                    let bookmarks = render_bookmarks(app);
+                    f.render_stateful_widget(bookmarks, chunks[1], &mut app.bookmarks_list_state);
+                },
+                AppInfo::MenuItem::Jobs => {
+                    let jobs = render_jobs(app, chunks[1].width);
+                    f.render_stateful_widget(jobs, chunks[1], &mut app.jobs_list_state);
+                },
+                AppInfo::MenuItem::RenamePreview => {
+                    let preview = render_rename_preview(app);
+                    f.render_stateful_widget(preview, chunks[1], &mut app.rename_list_state);
+                },
+                AppInfo::MenuItem::PatternRename => {
+                    let pattern_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([
+                        Constraint::Length(6),
+                        Constraint::Min(2),
+                        ].as_ref(),
+                    ).split(chunks[1]);
+
+                    f.render_widget(render_pattern_dialog(app), pattern_chunks[0]);
+                    let columns = render_rename_columns(app, pattern_chunks[1].width);
+                    f.render_stateful_widget(columns, pattern_chunks[1], &mut app.rename_list_state);
                 }
             }
             //////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
             
             //Bottom Bar
-            let bottom_bar = render_bottom_bar();
+            let bottom_bar = render_bottom_bar(app);
             f.render_widget(bottom_bar, chunks[2])
             //////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 }
 
 
-pub fn is_directory_empty(path: &String) -> bool {
-    if let Ok(entries) = fs::read_dir(path) {
-        return entries.count() == 0;
+/// Splits off the panel to the right of a listing, unless the preview is turned off.
+fn preview_split(app: &AppInfo::App, area: Rect) -> Vec<Rect> {
+    match app.config.preview.enabled {
+        true => Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+            .split(area),
+        false => vec![area],
     }
-    // If an error occurs while reading the directory, you can handle it accordingly.
-    false
 }
 
-pub fn path_exists(path: &String) -> bool{
-    fs::metadata(path).is_ok()
+/// Draws an entry's details, with the start of it underneath when it's a text file.
+fn render_preview<B: Backend>(f: &mut Frame<B>, app: &mut AppInfo::App, details: Paragraph, area: Rect) {
+    let path = app.selected_file.clone();
+
+    let text = match text_preview(app, &path) {
+        Some(data) => data,
+        None => {
+            f.render_widget(details, area);
+            return;
+        },
+    };
+
+    let preview_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(17), Constraint::Min(0)].as_ref())
+        .split(area);
+
+    f.render_widget(details, preview_chunks[0]);
+    f.render_widget(
+        Paragraph::new(text.into_iter().map(|line| Spans::from(Span::raw(line))).collect::<Vec<_>>())
+            .style(Style::default().fg(Color::Gray))
+            .block(Block::default().borders(Borders::TOP).title("Preview")),
+        preview_chunks[1],
+    );
 }
 
+// Only the metadata is read on every draw. The file itself is read again once it or the selection changes
+fn text_preview(app: &mut AppInfo::App, path: &str) -> Option<Vec<String>> {
+    let lines = app.config.preview.lines;
+    if lines == 0 {
+        return None;
+    }
+
+    let md = metadata(path).ok()?;
+    let key = (path.to_string(), lines, md.modified().ok(), md.len());
 
-pub fn strip_directory(path: &String) -> String{
-    path.split('/').last().unwrap().to_string()
+    if app.preview.as_ref().map(|preview| &preview.key) != Some(&key) {
+        let text = read_text_preview(path, &md, lines);
+        app.preview = Some(AppInfo::PreviewCache { key, text });
+    }
+
+    app.preview.as_ref()?.text.clone()
 }
 
-pub fn render_directory<'a>(app: &AppInfo::App) -> Result<(List<'a>, String), Box<dyn std::error::Error>> {
-    let md = fs::metadata(&app.current_directory)?;
+// The first lines of a file, or nothing for folders and files that look binary.
+// Anything else that isn't a regular file, like a FIFO or a device, isn't opened since reading it could block or have side effects
+fn read_text_preview(path: &str, md: &fs::Metadata, lines: usize) -> Option<Vec<String>> {
+    if md.is_dir() {
+        return None;
+    }
+    if !md.is_file() {
+        return Some(vec!["not a regular file".to_string()]);
+    }
+
+    let mut start = Vec::new();
+    fs::File::open(path).ok()?.take(16 * 1024).read_to_end(&mut start).ok()?;
+    if start.contains(&0) {
+        return None;
+    }
+
+    // Tabs are expanded since the terminal would otherwise jump past the panel
+    Some(String::from_utf8_lossy(&start).lines().take(lines).map(|line| line.replace('\t', "    ")).collect())
+}
+
+pub fn strip_directory(path: &str) -> String{
+    path.split('/').next_back().unwrap().to_string()
+}
 
-    if is_directory_empty(&app.current_directory) || !md.is_dir() {
-        let list = List::new(Vec::new());
+pub fn render_directory<'a>(app: &AppInfo::App) -> (Table<'a>, String) {
+    if app.entries.is_empty() {
+        let table = Table::new(Vec::new());
         let selected_dir = "";
-        return Ok((list, selected_dir.to_string()));
+        return (table, selected_dir.to_string());
     }
 
     let pets = Block::default()
@@ -107,71 +233,168 @@ pub fn render_directory<'a>(app: &AppInfo::App) -> Result<(List<'a>, String), Bo
         .style(Style::default().fg(Color::White))
         .border_type(BorderType::Plain);
 
-    let curr_dir = filesystem::util::get_files_in_directory(&app.current_directory)?;
-
-    let items: Vec<_> = curr_dir
+    let header = Row::new(SORT_COLUMNS.iter().map(|key| {
+        let label = match (app.sort.key == *key, app.sort.descending) {
+            (true, false) => format!("{} ^", key.label()),
+            (true, true) => format!("{} v", key.label()),
+            (false, _) => key.label().to_string(),
+        };
+        Cell::from(label)
+    }))
+    .style(Style::default().fg(app.config.colors.accent).add_modifier(Modifier::BOLD));
+
+    let rows: Vec<_> = app.entries
         .iter()
-        .map(|file| {
-            let tmp = strip_directory(file);
-            ListItem::new(Spans::from(vec![Span::styled(
-                tmp.clone(),
-                Style::default(),
-            )]))
+        .map(|entry| {
+            let (name, style) = match (app.marked.contains(&entry.path), entry.is_dir) {
+                (true, _) => (format!("* {}", entry.name), Style::default().fg(app.config.colors.marked).add_modifier(Modifier::BOLD)),
+                (false, true) => (format!("{}/", entry.name), Style::default().fg(app.config.colors.directory)),
+                (false, false) => (entry.name.clone(), Style::default()),
+            };
+            let size = match entry.is_dir {
+                true => "-".to_string(),
+                false => filesystem::util::human_size(entry.size),
+            };
+
+            Row::new(vec![
+                Cell::from(name),
+                Cell::from(size),
+                Cell::from(filesystem::util::format_time(entry.modified)),
+                Cell::from(entry.permissions.clone()),
+                Cell::from(entry.kind.clone()),
+            ])
+            .style(style)
         })
         .collect();
 
-    let selected_pet = curr_dir
+    let selected_pet = app.entries
         .get(
             app.directory_list_state
                 .selected()
                 .expect("there is always a selected pet"),
         )
-        .map(|pet| pet.clone())
-        .unwrap_or_else(|| String::new());
+        .map(|entry| entry.path.clone())
+        .unwrap_or_default();
+
+    let table = Table::new(rows)
+        .header(header)
+        .block(pets)
+        .widths(&[
+            Constraint::Percentage(35),
+            Constraint::Length(9),
+            Constraint::Length(19),
+            Constraint::Length(11),
+            Constraint::Length(7),
+        ])
+        .column_spacing(1)
+        .highlight_style(
+            Style::default()
+                .bg(app.config.colors.selection)
+                .fg(Color::Black)
+                .add_modifier(Modifier::BOLD),
+        );
+    (table, selected_pet)
+}
+
+/// The table's columns, in the order the number keys select them
+pub const SORT_COLUMNS: [filesystem::listing::SortKey; 5] = [
+    filesystem::listing::SortKey::Name,
+    filesystem::listing::SortKey::Size,
+    filesystem::listing::SortKey::Modified,
+    filesystem::listing::SortKey::Permissions,
+    filesystem::listing::SortKey::Type,
+];
+
+
+pub fn render_search_results<'a>(app: &AppInfo::App) -> Result<(List<'a>, String), Box<dyn std::error::Error>> {
+    let results = app.search_results.lock().unwrap_or_else(|e| e.into_inner());
+    let pets = Block::default()
+        .borders(Borders::RIGHT)
+        .style(Style::default().fg(Color::White))
+        .border_type(BorderType::Plain)
+        .title(match &app.search_error {
+            Some(e) => Span::styled(format!("Invalid query: {}", e), Style::default().fg(Color::Red)),
+            None => Span::raw(format!("{} found", results.len())),
+        });
+
+    let index = app.index.read().unwrap_or_else(|e| e.into_inner());
+    let matched = Style::default().fg(app.config.colors.matched).add_modifier(Modifier::BOLD);
+
+    let items: Vec<_> = results
+        .iter()
+        .map(|result| {
+            let relative = filesystem::index::relative_path(&index, &result.path);
+            let name_start = relative.chars().count() - strip_directory(relative).chars().count();
+
+            // One span per char, so the matched ones can be picked out
+            let spans: Vec<_> = relative
+                .chars()
+                .enumerate()
+                .map(|(i, c)| match (result.positions.contains(&i), i < name_start) {
+                    (true, _) => Span::styled(c.to_string(), matched),
+                    (false, true) => Span::styled(c.to_string(), Style::default().fg(Color::DarkGray)),
+                    (false, false) => Span::raw(c.to_string()),
+                })
+                .collect();
+
+            ListItem::new(Spans::from(spans))
+        })
+        .collect();
+
+    let selected_pet = results
+        .get(app.search_list_state.selected().unwrap_or(0))
+        .map(|result| result.path.clone())
+        .unwrap_or_default();
 
     let list = List::new(items).block(pets).highlight_style(
         Style::default()
-            .bg(Color::Yellow)
-            .fg(Color::Black)
+            .bg(Color::DarkGray)
             .add_modifier(Modifier::BOLD),
     );
     Ok((list, selected_pet))
 }
 
 
-pub fn render_search_results<'a>(app: &AppInfo::App) -> Result<(List<'a>, String), Box<dyn std::error::Error>> {
+/// Lists content search matches as `path:line: snippet`, with the match picked out
+pub fn render_grep_results<'a>(app: &AppInfo::App) -> Result<(List<'a>, String), Box<dyn std::error::Error>> {
+    let results = app.grep.results.lock().unwrap_or_else(|e| e.into_inner());
+    let searching = app.grep.job.is_some_and(|id| app.jobs.jobs.iter().any(|job| job.id == id && job.is_active()));
+
     let pets = Block::default()
         .borders(Borders::RIGHT)
         .style(Style::default().fg(Color::White))
-        .border_type(BorderType::Plain);
-
-    let search_term = app.message.clone();
-    let hash_search_results = filesystem::util::search_hash_map(search_term, &app.loaded_files).unwrap();
-
-    let items: Vec<_> = hash_search_results
+        .border_type(BorderType::Plain)
+        .title(match &app.grep.error {
+            Some(e) => Span::styled(format!("Invalid pattern: {}", e), Style::default().fg(Color::Red)),
+            None if searching => Span::raw(format!("{} matches, searching...", results.len())),
+            None => Span::raw(format!("{} matches", results.len())),
+        });
+
+    let items: Vec<_> = results
         .iter()
-        .map(|file| {
-            let tmp = strip_directory(file);
-            ListItem::new(Spans::from(vec![Span::styled(
-                tmp.clone(),
-                Style::default(),
-            )]))
+        .map(|result| {
+            let relative = result.path.strip_prefix(app.grep.root.as_str())
+                .map(|relative| relative.trim_start_matches('/'))
+                .unwrap_or(&result.path);
+            let (start, end) = result.matched;
+
+            ListItem::new(Spans::from(vec![
+                Span::styled(format!("{}:{}: ", relative, result.line), Style::default().fg(Color::DarkGray)),
+                Span::raw(result.snippet[..start].to_string()),
+                Span::styled(result.snippet[start..end].to_string(), Style::default().fg(app.config.colors.matched).add_modifier(Modifier::BOLD)),
+                Span::raw(result.snippet[end..].to_string()),
+            ]))
         })
         .collect();
 
-    let selected_pet = hash_search_results
-        .get(
-            app.search_list_state
-                .selected()
-                .expect("there is always a selected pet"),
-        )
-        .map(|pet| pet.clone())
-        .unwrap_or_else(|| String::new());
+    let selected_pet = results
+        .get(app.search_list_state.selected().unwrap_or(0))
+        .map(|result| result.path.clone())
+        .unwrap_or_default();
 
     let list = List::new(items).block(pets).highlight_style(
         Style::default()
-            .bg(Color::Yellow)
-            .fg(Color::Black)
+            .bg(Color::DarkGray)
             .add_modifier(Modifier::BOLD),
     );
     Ok((list, selected_pet))
@@ -180,50 +403,72 @@ pub fn render_search_results<'a>(app: &AppInfo::App) -> Result<(List<'a>, String
 
 pub fn render_search_results_widget<'a>(app: &mut AppInfo::App) -> (List<'a>, Paragraph<'a>){
 
-    let (directory_widget, selected_dir) = match render_search_results(app){
+    let results = match app.input_type {
+        AppInfo::InputType::Grep => render_grep_results(app),
+        _ => render_search_results(app),
+    };
+    let (directory_widget, selected_dir) = match results {
         Ok(data) => data,
-        Err(e) => panic!("test"),
+        Err(_) => panic!("test"),
     };
     
-    if path_exists(&selected_dir){
-        app.selected_file.clear();
-        app.selected_file.push_str(&selected_dir);
-    }
-    
-    let info_bar = render_details(&app.selected_file).unwrap();
+    let info_bar = render_details(&selected_dir).unwrap_or_else(|_| Paragraph::new(""));
 
     (directory_widget, info_bar)
 }
 
 
-pub fn render_search_bar<'a>( app: &'a AppInfo::App) -> (Paragraph<'a>){
+pub fn render_search_bar<'a>( app: &'a AppInfo::App) -> Paragraph<'a>{
+    let title = match app.input_type {
+        AppInfo::InputType::None => String::new(),
+        AppInfo::InputType::Searching => "Find file".to_string(),
+        AppInfo::InputType::Grep if app.grep.regex => "Find in files (regex)".to_string(),
+        AppInfo::InputType::Grep => "Find in files".to_string(),
+        AppInfo::InputType::MakeFile => "New file name, or end with / for a folder".to_string(),
+        AppInfo::InputType::MakeSymlink => format!("Symlink to {} named", strip_directory(&app.selected_file)),
+        AppInfo::InputType::MakeHardLink => format!("Hard link to {} named", strip_directory(&app.selected_file)),
+        AppInfo::InputType::RenameFile => match app.rename_queue.first() {
+            Some(original) if app.rename_queue.len() > 1 => format!("Rename {} to ({} left)", strip_directory(original), app.rename_queue.len()),
+            Some(original) => format!("Rename {} to", strip_directory(original)),
+            None => "Rename to".to_string(),
+        },
+        AppInfo::InputType::DeletePermanently => format!("Delete {} item(s) permanently? This can't be undone (y/n)", app.targets().len()),
+        AppInfo::InputType::EmptyTrash => "Empty the trash? This can't be undone (y/n)".to_string(),
+        AppInfo::InputType::SelectGlob => "Mark entries matching".to_string(),
+        AppInfo::InputType::ConflictRename => "Use the name instead".to_string(),
+        AppInfo::InputType::Command => "Command".to_string(),
+        AppInfo::InputType::SetBookmark => "Bookmark this folder as (press a key)".to_string(),
+        AppInfo::InputType::JumpToBookmark if app.bookmarks.is_empty() => "Go to bookmark (none yet, m and a key sets one)".to_string(),
+        AppInfo::InputType::JumpToBookmark => format!("Go to bookmark ({})", app.bookmarks.iter().map(|bookmark| bookmark.mark.to_string()).collect::<Vec<_>>().join(" ")),
+        AppInfo::InputType::RenameBookmark => "Rename bookmark to".to_string(),
+        AppInfo::InputType::Jump => match app.frecency.best(&app.input, &app.current_directory, filesystem::frecency::now()) {
+            Some(directory) => format!("Jump to {}", directory),
+            None if app.input.trim().is_empty() => "Jump to a visited folder matching".to_string(),
+            None => "Jump to (no visited folder matches)".to_string(),
+        },
+    };
+
     let input = Paragraph::new(app.input.as_ref())
         .style(match app.input_mode {
             AppInfo::InputMode::Normal => Style::default(),
             AppInfo::InputMode::Typing => Style::default().fg(Color::Yellow),
         })
-        .block(Block::default().borders(Borders::ALL));
+        .block(Block::default().borders(Borders::ALL).title(title));
 
     input
 }
 
 
 
-pub fn render_file_widget<'a>(app: &mut AppInfo::App) -> (List<'a>, Paragraph<'a>){
+pub fn render_file_widget<'a>(app: &mut AppInfo::App) -> (Table<'a>, Paragraph<'a>){
 
-    let (directory_widget, selected_dir) = match render_directory(app){
-        Ok(data) => data,
-        Err(e) => panic!("{:?}", e),
-    };
-    
-    if path_exists(&selected_dir){
-        app.selected_file.clear();
-        app.selected_file.push_str(&selected_dir);
-    }
+    let (directory_widget, selected_dir) = render_directory(app);
     
+    // The listing is kept up to date, so an empty selection means the folder is empty
+    app.selected_file.clear();
+    app.selected_file.push_str(&selected_dir);
 
-
-    let info_bar = render_details(&app.selected_file).unwrap();
+    let info_bar = render_details(&app.selected_file).unwrap_or_else(|_| Paragraph::new(""));
 
     (directory_widget, info_bar)
 }
@@ -240,9 +485,452 @@ pub fn render_directory_display<'a>( directory: &String) -> Paragraph<'a> {
     )
 }
 
-pub fn render_bottom_bar<'a>() -> Paragraph<'a> {
-    Paragraph::new("N Create    C Copy  X Cut   V Paste   R Rename  D Delete    O Open / Search ")
-        .style(Style::default().fg(Color::LightGreen))
+pub fn render_trash<'a>(app: &AppInfo::App) -> List<'a> {
+    let items: Vec<_> = app.trash_entries
+        .iter()
+        .map(|entry| {
+            ListItem::new(Spans::from(vec![
+                Span::styled(entry.deletion_date.replace('T', " "), Style::default().fg(Color::DarkGray)),
+                Span::raw("  "),
+                Span::raw(entry.original_path.clone()),
+            ]))
+        })
+        .collect();
+
+    List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Trash"),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(app.config.colors.selection)
+                .fg(Color::Black)
+                .add_modifier(Modifier::BOLD),
+        )
+}
+
+pub fn render_bookmarks<'a>(app: &AppInfo::App) -> List<'a> {
+    let items: Vec<_> = app.bookmarks
+        .iter()
+        .map(|bookmark| {
+            ListItem::new(Spans::from(vec![
+                Span::styled(bookmark.mark.to_string(), Style::default().fg(app.config.colors.accent).add_modifier(Modifier::BOLD)),
+                Span::raw("  "),
+                Span::raw(bookmark.name.clone()),
+                Span::raw("  "),
+                Span::styled(bookmark.path.clone(), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+
+    List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Bookmarks"),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(app.config.colors.selection)
+                .fg(Color::Black)
+                .add_modifier(Modifier::BOLD),
+        )
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn render_job<'a>(job: &Job, width: u16) -> ListItem<'a> {
+    let ratio = job.progress.ratio();
+    let bar_width = (width as usize / 4).max(10);
+    let filled = (ratio * bar_width as f64) as usize;
+
+    let (state, color) = match &job.state {
+        JobState::Running => ("running".to_string(), Color::LightGreen),
+        JobState::Paused => ("paused".to_string(), Color::Yellow),
+        JobState::Done => ("done".to_string(), Color::Gray),
+        JobState::Cancelled => ("cancelled".to_string(), Color::Gray),
+        JobState::Failed(e) => (format!("failed: {}", e), Color::Red),
+    };
+
+    let eta = match job.eta() {
+        Some(eta) => format!("ETA {}", format_duration(eta)),
+        None => String::new(),
+    };
+
+    ListItem::new(vec![
+        Spans::from(vec![
+            Span::styled(format!("#{} ", job.id), Style::default().fg(Color::DarkGray)),
+            Span::raw(job.description.clone()),
+            Span::styled(format!("  {}", state), Style::default().fg(color)),
+        ]),
+        Spans::from(vec![
+            Span::styled(format!("[{}{}]", "#".repeat(filled), "-".repeat(bar_width - filled)), Style::default().fg(color)),
+            Span::raw(format!(
+                " {:>3.0}%  {:.1}/{:.1}MB  {}/{} files  {}",
+                ratio * 100.0,
+                job.progress.bytes_done.load(Ordering::Relaxed) as f64 / (1024.0 * 1024.0),
+                job.progress.bytes_total.load(Ordering::Relaxed) as f64 / (1024.0 * 1024.0),
+                job.progress.files_done.load(Ordering::Relaxed),
+                job.progress.files_total.load(Ordering::Relaxed),
+                eta,
+            )),
+        ]),
+    ])
+}
+
+pub fn render_jobs<'a>(app: &AppInfo::App, width: u16) -> List<'a> {
+    let items: Vec<_> = app.jobs.jobs
+        .iter()
+        .map(|job| render_job(job, width))
+        .collect();
+
+    List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Jobs"),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+}
+
+pub fn render_rename_preview<'a>(app: &AppInfo::App) -> List<'a> {
+    let plan = match &app.rename_plan {
+        Some(data) => data,
+        None => return List::new(Vec::new()),
+    };
+
+    let mut items: Vec<_> = plan.problems
+        .iter()
+        .map(|problem| ListItem::new(Spans::from(vec![Span::styled(problem.clone(), Style::default().fg(Color::Red))])))
+        .collect();
+
+    items.extend(plan.changes.iter().map(|(source, target)| {
+        ListItem::new(Spans::from(vec![
+            Span::raw(strip_directory(source)),
+            Span::styled("  ->  ", Style::default().fg(Color::DarkGray)),
+            Span::styled(target.file_name().unwrap_or_default().to_string_lossy().into_owned(), Style::default().fg(Color::LightGreen)),
+        ]))
+    }));
+
+    let title = match (plan.problems.len(), plan.cycles) {
+        (0, 0) => format!("Rename {} item(s)", plan.changes.len()),
+        (0, cycles) => format!("Rename {} item(s), {} cycle(s) go through a temporary name", plan.changes.len(), cycles),
+        (problems, _) => format!("{} problem(s) to fix before renaming", problems),
+    };
+
+    List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title(title),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+}
+
+pub fn render_pattern_dialog<'a>(app: &AppInfo::App) -> Paragraph<'a> {
+    let field_style = |field: AppInfo::PatternField| match app.pattern_field == field {
+        true => Style::default().fg(Color::Yellow),
+        false => Style::default(),
+    };
+    let pattern = &app.rename_pattern;
+
+    let summary = match &app.rename_plan {
+        Some(plan) if !plan.problems.is_empty() => Span::styled(
+            format!("{} ({} problem(s))", plan.problems[0], plan.problems.len()),
+            Style::default().fg(Color::Red),
+        ),
+        Some(plan) => Span::styled(format!("{} of {} name(s) change", plan.changes.len(), plan.sources.len()), Style::default().fg(Color::LightGreen)),
+        None => Span::raw(""),
+    };
+
+    Paragraph::new(vec![
+        Spans::from(vec![Span::raw("Find:    "), Span::styled(pattern.find.clone(), field_style(AppInfo::PatternField::Find))]),
+        Spans::from(vec![Span::raw("Replace: "), Span::styled(pattern.replace.clone(), field_style(AppInfo::PatternField::Replace))]),
+        Spans::from(vec![Span::styled(
+            format!(
+                "[{}] Regex (Ctrl+X)   Case: {} (Ctrl+T)   {{n}} {{n:03}} counter   $1 group",
+                if pattern.regex { "x" } else { " " },
+                pattern.case.label(),
+            ),
+            Style::default().fg(Color::DarkGray),
+        )]),
+        Spans::from(vec![summary]),
+        ])
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Pattern rename"),
+        )
+}
+
+/// Lists every name next to what it will become
+pub fn render_rename_columns<'a>(app: &AppInfo::App, width: u16) -> List<'a> {
+    let plan = match &app.rename_plan {
+        Some(data) => data,
+        None => return List::new(Vec::new()),
+    };
+    let column = (width as usize).saturating_sub(2) / 2;
+
+    let items: Vec<_> = plan.sources
+        .iter()
+        .zip(&plan.names)
+        .map(|(source, name)| {
+            let old = strip_directory(source);
+            let style = match old == *name {
+                true => Style::default().fg(Color::DarkGray),
+                false => Style::default().fg(Color::LightGreen),
+            };
+
+            ListItem::new(Spans::from(vec![
+                Span::raw(format!("{:<width$}", old, width = column)),
+                Span::styled(name.clone(), style),
+            ]))
+        })
+        .collect();
+
+    List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Old / New"),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+}
+
+/// Lists the actions matching what's typed in the command palette, each with its keys
+pub fn render_palette<'a>(app: &AppInfo::App) -> List<'a> {
+    let found = palette::matches(&app.input);
+    let matched = Style::default().fg(app.config.colors.matched).add_modifier(Modifier::BOLD);
+
+    let title = match palette::parse(&app.input) {
+        Ok(Some(_)) => Span::raw("Enter to run"),
+        Ok(None) => Span::raw(format!("{} actions", found.len())),
+        Err(e) => Span::styled(e.to_string(), Style::default().fg(Color::Red)),
+    };
+
+    let items: Vec<_> = found
+        .iter()
+        .map(|(action, positions)| {
+            let mut spans: Vec<_> = action
+                .label()
+                .chars()
+                .enumerate()
+                .map(|(i, c)| match positions.contains(&i) {
+                    true => Span::styled(c.to_string(), matched),
+                    false => Span::raw(c.to_string()),
+                })
+                .collect();
+
+            if let Some(keys) = app.keymap.keys_for(*action) {
+                spans.push(Span::styled(format!("   {}", keys.label()), Style::default().fg(Color::DarkGray)));
+            }
+
+            ListItem::new(Spans::from(spans))
+        })
+        .collect();
+
+    List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title(title),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+}
+
+/// Returns a rectangle of the given width percentage and height, centred in `area`
+pub fn centered_rect(percent_x: u16, height: u16, area: Rect) -> Rect {
+    let width = area.width * percent_x / 100;
+    let height = height.min(area.height);
+
+    Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height)
+}
+
+fn describe_entry(path: &str) -> String {
+    match metadata(path) {
+        Ok(md) => format!(
+            "{:.2}MB, modified {}",
+            filesystem::util::get_size_in_mb(&md).unwrap_or_default(),
+            filesystem::util::last_modified_time(&md).unwrap_or_default(),
+        ),
+        Err(_) => "unknown".to_string(),
+    }
+}
+
+pub fn render_conflict_dialog<'a>(app: &AppInfo::App) -> Paragraph<'a> {
+    let operation = match app.operations.pending.front() {
+        Some(data) => data,
+        None => return Paragraph::new(""),
+    };
+
+    let destination = operation.destination.to_string_lossy().into_owned();
+    let apply_to_all = if app.operations.apply_to_all { "[x]" } else { "[ ]" };
+    // Not on disk yet means an earlier item in the same batch is going there
+    let (headline, existing) = match std::fs::symlink_metadata(&destination) {
+        Ok(_) => (format!("{} already exists", destination), describe_entry(&destination)),
+        Err(_) => (format!("{} is already the destination of another item", destination), "not created yet".to_string()),
+    };
+
+    Paragraph::new(vec![
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::styled(headline, Style::default().add_modifier(Modifier::BOLD))]),
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::raw(format!("Existing: {}", existing))]),
+        Spans::from(vec![Span::raw(format!("Incoming: {}", describe_entry(&operation.source)))]),
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::styled("O Overwrite   S Skip   K Keep Both   R Rename", Style::default().fg(Color::LightGreen))]),
+        Spans::from(vec![Span::styled(
+            format!("A {} Apply to all {} remaining   Esc Cancel", apply_to_all, app.operations.pending.len()),
+            Style::default().fg(Color::LightGreen),
+        )]),
+        ])
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Conflict"),
+        )
+}
+
+pub fn render_permissions_dialog<'a>(dialog: &AppInfo::PermissionsDialog) -> Paragraph<'a> {
+    let focused = |focus: AppInfo::PermissionsFocus| match dialog.focus == focus {
+        true => Style::default().fg(Color::Yellow),
+        false => Style::default(),
+    };
+    let checkbox = |cell: usize| {
+        let mark = if dialog.mode & AppInfo::PermissionsDialog::bit(cell) != 0 { "[x]" } else { "[ ]" };
+        let style = match dialog.focus == AppInfo::PermissionsFocus::Checkboxes && dialog.cursor == cell {
+            true => Style::default().add_modifier(Modifier::REVERSED),
+            false => Style::default(),
+        };
+        Span::styled(format!("{:<8}", mark), style)
+    };
+    let row = |label: &str, first: usize| {
+        let mut spans = vec![Span::raw(format!("  {:<10}", label))];
+        spans.extend((first..first + 3).map(checkbox));
+        Spans::from(spans)
+    };
+    let header = |labels: [&str; 3]| Spans::from(vec![Span::styled(
+        format!("  {:<10}{:<8}{:<8}{:<8}", "", labels[0], labels[1], labels[2]),
+        Style::default().fg(Color::DarkGray),
+    )]);
+
+    let targets = match dialog.targets.as_slice() {
+        [single] => strip_directory(single),
+        targets => format!("{} items", targets.len()),
+    };
+
+    Paragraph::new(vec![
+        Spans::from(vec![Span::styled(format!("  {}", targets), Style::default().add_modifier(Modifier::BOLD))]),
+        Spans::from(vec![Span::raw("")]),
+        header(["Read", "Write", "Execute"]),
+        row("User", 0),
+        row("Group", 3),
+        row("Other", 6),
+        header(["Setuid", "Setgid", "Sticky"]),
+        row("Special", 9),
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![
+            Span::raw("  Octal: "),
+            Span::styled(format!("{:<6}", dialog.octal), focused(AppInfo::PermissionsFocus::Octal)),
+            Span::styled(filesystem::permissions::mode_bits(dialog.mode), Style::default().fg(Color::DarkGray)),
+        ]),
+        Spans::from(vec![
+            Span::raw("  Owner: "),
+            Span::styled(dialog.owner.clone(), focused(AppInfo::PermissionsFocus::Owner)),
+            Span::styled(format!("  (now {}, type user:group to change)", dialog.current_owner), Style::default().fg(Color::DarkGray)),
+        ]),
+        Spans::from(vec![Span::raw(format!("  {} Recursive", if dialog.recursive { "[x]" } else { "[ ]" }))]),
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::styled("  Space Toggle   Tab Next Field   Ctrl+R Recursive   Enter Apply   Esc Cancel", Style::default().fg(Color::LightGreen))]),
+        ])
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Permissions"),
+        )
+}
+
+// The listing's hints follow the keymap, so they show whatever the keys were changed to
+fn keymap_hints(app: &AppInfo::App) -> String {
+    let mut hints: Vec<String> = Action::HINTS
+        .iter()
+        .filter_map(|&action| app.keymap.keys_for(action).map(|keys| (keys.label(), action.label())))
+        // Nothing to learn from `Up Up`, but a preset's `k Up` is worth showing
+        .filter(|(keys, label)| keys != label)
+        .map(|(keys, label)| format!("{} {}", keys, label))
+        .collect();
+
+    if !app.pending_keys.is_empty() {
+        let pending: Vec<String> = app.pending_keys.iter().map(Key::label).collect();
+        hints.insert(0, format!("[{}]", pending.join(" ")));
+    }
+
+    hints.join("   ")
+}
+
+pub fn render_bottom_bar<'a>(app: &AppInfo::App) -> Paragraph<'a> {
+    // The other views aren't in the keymap, so these match the fixed keys their handlers use
+    let keys = match app.active_menu_item {
+        AppInfo::MenuItem::Trash => "R Restore   D Delete Permanently   E Empty Trash   Up/Down Select   Esc Back".to_string(),
+        AppInfo::MenuItem::Bookmarks => "Enter Go To   R Rename   D Delete   Up/Down Select   Esc Back".to_string(),
+        AppInfo::MenuItem::Jobs => "P Pause/Resume   C Cancel   X Clear Finished   Up/Down Select   Esc Back".to_string(),
+        AppInfo::MenuItem::RenamePreview => "Enter Apply   E Edit Again   Esc Cancel".to_string(),
+        AppInfo::MenuItem::Search if matches!(app.input_type, AppInfo::InputType::Grep) => "Enter Search / Open in Editor   Ctrl+X Regex   Tab Find File   Up/Down Select   Esc Back".to_string(),
+        AppInfo::MenuItem::Search => "Tab Find in Files   Fuzzy Words or ext: size: modified: type: path: name: with AND OR NOT   Up/Down Select   Enter Go To   Esc Back".to_string(),
+        AppInfo::MenuItem::Palette => "Type to filter, or mkdir <name>   cd <folder>   sort <column> [asc|desc]   z <words>   import zoxide|autojump   Up/Down Select   Enter Run   Esc Back".to_string(),
+        AppInfo::MenuItem::PatternRename => "Enter Apply   Tab Switch Field   Ctrl+X Regex   Ctrl+T Case   Esc Cancel".to_string(),
+        _ => keymap_hints(app),
+    };
+
+    let running: Vec<_> = app.jobs.jobs.iter().filter(|job| job.is_active()).collect();
+    let jobs = match running.as_slice() {
+        [] => String::new(),
+        [job] => format!("[Job {:.0}%]  ", job.progress.ratio() * 100.0),
+        jobs => format!("[{} jobs {:.0}%]  ", jobs.len(), jobs.iter().map(|job| job.progress.ratio()).sum::<f64>() * 100.0 / jobs.len() as f64),
+    };
+
+    let marked = match app.marked.len() {
+        0 => String::new(),
+        amount => format!("[{} marked]  ", amount),
+    };
+
+    let clipboard = match (app.clipboard.paths.as_slice(), app.clipboard.mode) {
+        ([], _) => String::new(),
+        ([path], AppInfo::ClipboardMode::Copy) => format!("[Copied: {}]  ", strip_directory(path)),
+        ([path], AppInfo::ClipboardMode::Cut) => format!("[Cut: {}]  ", strip_directory(path)),
+        (paths, AppInfo::ClipboardMode::Copy) => format!("[Copied: {} items]  ", paths.len()),
+        (paths, AppInfo::ClipboardMode::Cut) => format!("[Cut: {} items]  ", paths.len()),
+    };
+
+    Paragraph::new(vec![
+        Spans::from(vec![Span::raw(keys)]),
+        Spans::from(vec![
+            Span::styled(jobs, Style::default().fg(Color::LightGreen)),
+            Span::styled(marked, Style::default().fg(app.config.colors.marked)),
+            Span::styled(clipboard, Style::default().fg(Color::Yellow)),
+            Span::styled(app.status.clone(), Style::default().fg(Color::Gray)),
+        ]),
+        ])
+        .style(Style::default().fg(app.config.colors.accent))
         .alignment(Alignment::Left)
         .block(
             Block::default()
@@ -253,9 +941,9 @@ pub fn render_bottom_bar<'a>() -> Paragraph<'a> {
 
 
 pub fn render_details<'a>(path: &String) -> Result<Paragraph<'a>, Box<dyn std::error::Error>>{
-    let md   = metadata(path).unwrap();
+    let md   = metadata(path).or_else(|_| fs::symlink_metadata(path))?;
 
-    let file_name = filesystem::util::file_name(path).unwrap();
+    let file_name = filesystem::util::file_name(path).unwrap_or_default();
     let file_type = filesystem::util::is_path_file(&md).unwrap();
     let file_size = filesystem::util::get_size_in_mb(&md).unwrap();
     let file_modify_time = filesystem::util::last_modified_time(&md).unwrap();
@@ -265,6 +953,11 @@ pub fn render_details<'a>(path: &String) -> Result<Paragraph<'a>, Box<dyn std::e
     let file_size_string = format!("File Size: {}MB",file_size);
     let file_modify_time_string = format!("Last Time Modified: {}",file_modify_time);
 
+    let link_md = fs::symlink_metadata(path)?;
+    let mode_string = format!("Mode: {} ({:04o})", filesystem::permissions::mode_string(&link_md), link_md.mode() & 0o7777);
+    let owner_string = format!("Owner: {}", filesystem::permissions::owner_string(&link_md));
+    let inode_string = format!("Inode: {}  Links: {}", link_md.ino(), link_md.nlink());
+
     let home = Paragraph::new(vec![
         Spans::from(vec![Span::raw("")]),
         Spans::from(vec![Span::raw(file_name_string)]),
@@ -275,6 +968,13 @@ pub fn render_details<'a>(path: &String) -> Result<Paragraph<'a>, Box<dyn std::e
         Spans::from(vec![Span::raw("")]),
         Spans::from(vec![Span::raw(file_modify_time_string)]),
         Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::raw(mode_string)]),
+        Spans::from(vec![Span::raw(owner_string)]),
+        Spans::from(vec![Span::raw(inode_string)]),
+        Spans::from(vec![Span::raw(match fs::read_link(path) {
+            Ok(target) => format!("Link Target: {}", target.display()),
+            Err(_) => String::new(),
+        })]),
         Spans::from(vec![Span::styled(
             "Rust-CLI FileExplorer",
             Style::default().fg(Color::LightBlue),
@@ -0,0 +1,54 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::filesystem::util;
+use crate::Error;
+
+/// A folder saved under a single key, so `' a` can jump back to it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Bookmark {
+    pub mark: char,
+    pub name: String,
+    pub path: String,
+}
+
+/// Returns where bookmarks are kept, `$XDG_DATA_HOME/rfe/bookmarks.json`.
+pub fn bookmarks_path() -> PathBuf {
+    util::xdg_data_home().join("rfe").join("bookmarks.json")
+}
+
+/// Reads the saved bookmarks, none when nothing has been bookmarked yet.
+pub fn load() -> Result<Vec<Bookmark>, Error> {
+    let contents = match fs::read_to_string(bookmarks_path()) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(serde_json::from_str(&contents)?)
+}
+
+pub fn save(bookmarks: &[Bookmark]) -> Result<(), Error> {
+    let path = bookmarks_path();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(bookmarks)?)?;
+
+    Ok(())
+}
+
+/// Saves `path` under `mark`, replacing whatever was there. Bookmarks are kept in mark order.
+pub fn set(bookmarks: &mut Vec<Bookmark>, mark: char, path: &str) {
+    bookmarks.retain(|bookmark| bookmark.mark != mark);
+    bookmarks.push(Bookmark {
+        mark,
+        name: util::file_name(path).unwrap_or_else(|| path.to_string()),
+        path: path.to_string(),
+    });
+    bookmarks.sort_by_key(|bookmark| bookmark.mark);
+}
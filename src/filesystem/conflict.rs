@@ -0,0 +1,157 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::filesystem::{explorer, util};
+use crate::jobs::JobProgress;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Operation {
+    Copy,
+    Move,
+    Rename,
+}
+
+/// A copy, move or rename that hasn't run yet, so its destination can still be changed.
+pub struct PendingOperation {
+    pub operation: Operation,
+    pub source: String,
+    pub destination: PathBuf,
+    // Set once the user chose to overwrite. The existing entry is only trashed when the operation runs
+    pub overwrite: bool,
+}
+
+/// What to do when the destination of an operation already exists.
+#[derive(Clone, PartialEq)]
+pub enum Resolution {
+    Overwrite,
+    Skip,
+    KeepBoth,
+    Rename(String),
+}
+
+impl Resolution {
+    /// Parses the `--on-conflict` values used by batch mode.
+    pub fn parse(value: &str) -> Option<Resolution> {
+        match value {
+            "overwrite" => Some(Resolution::Overwrite),
+            "skip" => Some(Resolution::Skip),
+            "keep-both" => Some(Resolution::KeepBoth),
+            _ => None,
+        }
+    }
+}
+
+/// Builds the operations for pasting clipboard entries into a folder.
+pub fn plan_paste(paths: &[String], cut: bool, directory: &str) -> Vec<PendingOperation> {
+    paths
+        .iter()
+        .map(|source| PendingOperation {
+            operation: if cut { Operation::Move } else { Operation::Copy },
+            source: source.clone(),
+            destination: Path::new(directory).join(util::strip_directory(source)),
+            overwrite: false,
+        })
+        .collect()
+}
+
+/// Whether the destination already exists, or is the destination of an operation planned earlier in the same batch.
+pub fn has_conflict(operation: &PendingOperation, planned: &[PathBuf]) -> bool {
+    !operation.overwrite && (planned.contains(&operation.destination) || fs::symlink_metadata(&operation.destination).is_ok())
+}
+
+/// Resolves conflicts that don't need asking, like pasting a copy next to its original.
+pub fn automatic_resolution(operation: &PendingOperation) -> Option<Resolution> {
+    if !is_same_path(Path::new(&operation.source), &operation.destination) {
+        return None;
+    }
+
+    match operation.operation {
+        Operation::Copy => Some(Resolution::KeepBoth),
+        Operation::Move | Operation::Rename => Some(Resolution::Skip),
+    }
+}
+
+/// Applies a resolution to the operation, returning false when it should be skipped.
+/// Nothing on disk changes until the operation runs.
+pub fn resolve(operation: &mut PendingOperation, resolution: &Resolution, planned: &[PathBuf]) -> Result<bool, Error> {
+    match resolution {
+        Resolution::Skip => return Ok(false),
+        Resolution::KeepBoth => operation.destination = keep_both_path(&operation.destination, planned),
+        Resolution::Rename(name) => operation.destination = operation.destination.with_file_name(name),
+        Resolution::Overwrite => {
+            if is_same_path(Path::new(&operation.source), &operation.destination) {
+                return Err(Error::new(ErrorKind::InvalidInput, "can't overwrite an entry with itself"));
+            }
+            operation.overwrite = true;
+        },
+    }
+
+    Ok(true)
+}
+
+pub fn run(operation: &PendingOperation, progress: &JobProgress) -> Result<(), Error> {
+    let source = Path::new(&operation.source);
+
+    // The replaced entry goes to the trash so an overwrite can still be taken back
+    if operation.overwrite && fs::symlink_metadata(&operation.destination).is_ok() {
+        explorer::delete_file(&operation.destination.to_string_lossy(), progress)?;
+    }
+
+    match operation.operation {
+        Operation::Copy => explorer::copy_recursive(source, &operation.destination, progress),
+        Operation::Move => explorer::move_path(source, &operation.destination, progress),
+        Operation::Rename => {
+            explorer::rename_file(source, &operation.destination)?;
+            progress.add_done(0, 1);
+            Ok(())
+        },
+    }
+}
+
+/// Runs a list of resolved operations as one job, measuring everything up front for the progress bar.
+pub fn run_all(operations: &[PendingOperation], progress: &JobProgress) -> Result<(), Error> {
+    for operation in operations {
+        match operation.operation {
+            Operation::Rename => progress.add_total(0, 1),
+            _ => {
+                let (bytes, files) = util::measure(Path::new(&operation.source));
+                progress.add_total(bytes, files);
+            },
+        }
+        // Trashing what's overwritten counts too, since it can mean copying it to another device
+        if operation.overwrite && fs::symlink_metadata(&operation.destination).is_ok() {
+            let (bytes, files) = util::measure(&operation.destination);
+            progress.add_total(bytes, files);
+        }
+    }
+
+    for operation in operations {
+        run(operation, progress)?;
+    }
+
+    Ok(())
+}
+
+/// Finds the first free `name (n).ext` next to an existing entry, that isn't already `planned` either.
+pub fn keep_both_path(path: &Path, planned: &[PathBuf]) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+
+    let mut counter = 1;
+    loop {
+        let candidate = path.with_file_name(format!("{} ({}){}", stem, counter, extension));
+
+        if !planned.contains(&candidate) && fs::symlink_metadata(&candidate).is_err() {
+            return candidate;
+        }
+        counter += 1;
+    }
+}
+
+fn is_same_path(a: &Path, b: &Path) -> bool {
+    match (std::path::absolute(a), std::path::absolute(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
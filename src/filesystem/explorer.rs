@@ -1,12 +1,12 @@
-use std::{fs, path};
-use std::fs::read_dir;
-use std::ops::Deref;
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::io::{Error, Write};
-use std::fs::File;
-use std::env;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::fs::{File, FileTimes, OpenOptions};
+use std::os::unix::fs::{symlink, FileTypeExt};
 
-use crate::filesystem::util;
+use crate::filesystem::journal::{self, Change};
+use crate::filesystem::{trash, util};
+use crate::jobs::JobProgress;
 
 /// Opens a file at the given path. Returns a string if there was an error.
 // NOTE(conaticus): I tried handling the errors nicely here but Tauri was mega cringe and wouldn't let me nest results in async functions, so used string error messages instead.
@@ -14,83 +14,218 @@ pub fn open_file(path: String) -> Result<(), Error> {
     open::that(path)
 }
 
-pub fn delete_file(path: &String) -> Result<(), Error>{
-    let _ = match fs::remove_file(path){
-        Ok(data) => data,
-        Err(e) => panic!("Could not delete file, Error {}", e),
-    };
+/// Moves a file or folder to the trash so it can be restored later.
+pub fn delete_file(path: &str, progress: &JobProgress) -> Result<(), Error>{
+    let trashed = trash::trash_path(path, progress)?;
+    journal::record(Change::Trash { original: Path::new(path).to_path_buf(), trashed });
 
     Ok(())
 }
 
-pub fn make_file(path: &String, file_name: &String) -> Result<(), Error>{
-    let full_path = format!("{}/{}", path, file_name);
+/// Puts a trashed entry back where it was deleted from.
+pub fn restore_file(entry: &trash::TrashEntry, progress: &JobProgress) -> Result<(), Error> {
+    trash::restore(entry, progress)?;
+    journal::record(Change::Restore { trashed: entry.trashed_path.clone(), original: PathBuf::from(&entry.original_path) });
 
-    let mut file = match File::create(&full_path){
-        Ok(data) => data,
-        Err(e) => panic!("Could not create file, Error {}", e),
-    };
+    Ok(())
+}
 
-    file.write_all(b"Initial content\n")?;
+/// Deletes a file or folder for good, skipping the trash.
+pub fn delete_permanently(path: &str, progress: &JobProgress) -> Result<(), Error>{
+    remove_recursive(Path::new(path), progress)
+}
+
+/// Removes a file or folder one entry at a time so the removal can be tracked and cancelled.
+pub fn remove_recursive(path: &Path, progress: &JobProgress) -> Result<(), Error> {
+    progress.checkpoint()?;
+    let md = fs::symlink_metadata(path)?;
+
+    if md.is_dir() {
+        for entry in fs::read_dir(path)? {
+            remove_recursive(&entry?.path(), progress)?;
+        }
+        return fs::remove_dir(path);
+    }
+
+    fs::remove_file(path)?;
+    progress.add_done(md.len(), 1);
 
     Ok(())
 }
 
-pub fn rename_file(original_file: &String, path: &String, name: &String) -> Result<(), Error>{
-    let full_path = format!("{}/{}", path, name);
+/// Creates an empty file, or a folder when the name ends with `/`, along with any missing parent folders.
+pub fn make_file(path: &String, file_name: &String) -> Result<PathBuf, Error>{
+    let full_path = Path::new(path).join(file_name);
 
-    let res = match fs::rename(original_file, full_path){
-        Ok(data) => data,
-        Err(e) => panic!("Could not rename file, Error: {}", e),
-    };
+    journal::batch(&format!("Create {}", file_name), || {
+        make_parents(&full_path)?;
 
-    Ok(())
+        let directory = file_name.ends_with('/');
+        match directory {
+            true => fs::create_dir(&full_path)?,
+            false => File::create_new(&full_path).map(|_| ())?,
+        }
+        journal::record(Change::Create { path: full_path.clone(), directory });
+
+        Ok(full_path.clone())
+    })
 }
 
-pub fn copy_file_to_cache(file_path: &str) -> std::io::Result<()> {
+/// Creates a symlink or hard link at `path` pointing to `target`, along with any missing parent folders.
+pub fn make_link(target: &Path, path: &Path, hard: bool) -> Result<(), Error> {
+    let target = std::path::absolute(target)?;
+
+    journal::batch(&format!("Link {}", util::strip_directory(&path.to_string_lossy())), || {
+        make_parents(path)?;
+
+        match hard {
+            true => fs::hard_link(&target, path)?,
+            false => symlink(&target, path)?,
+        }
+        journal::record(Change::Link { target: target.clone(), path: path.to_path_buf(), hard });
 
-    let cache_dir = util::get_cahce_fodler().unwrap();
-    
-    // Create the cache directory if it doesn't exist
-    let _ =  fs::create_dir_all(&cache_dir)?;
-    
-    // Get the file name from the original file path
-    let file_name = util::strip_directory(file_path);
+        Ok(())
+    })
+}
 
-    // Construct the destination file path
-    let dest_path = format!("{}\\{}", cache_dir, file_name);
+/// Creates the missing folders above `path` one at a time, so each one can be undone.
+fn make_parents(path: &Path) -> Result<(), Error> {
+    let missing: Vec<_> = path
+        .ancestors()
+        .skip(1)
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .take_while(|ancestor| fs::symlink_metadata(ancestor).is_err())
+        .collect();
 
-    // Copy the file to the cache directory
-    fs::copy(file_path, dest_path)?;
+    for directory in missing.into_iter().rev() {
+        fs::create_dir(directory)?;
+        journal::record(Change::Create { path: directory.to_path_buf(), directory: true });
+    }
 
     Ok(())
 }
 
-pub fn cut_file(path: &String) -> Result<(), Error>{
+/// Renames an entry to a full destination path, which doesn't have to be in the current directory.
+pub fn rename_file(original_file: &Path, destination: &Path) -> Result<(), Error>{
+    fs::rename(original_file, destination)?;
+    journal::record(Change::Rename { from: original_file.to_path_buf(), to: destination.to_path_buf() });
+
+    Ok(())
+}
 
-    let _ = copy_file_to_cache(path);
-    let _ = delete_file(path);
+/// Copies a file, symlink or whole folder, keeping permissions and timestamps.
+pub fn copy_recursive(source: &Path, destination: &Path, progress: &JobProgress) -> Result<(), Error> {
+    copy_tree(source, destination, progress)?;
+    journal::record(Change::Copy { from: source.to_path_buf(), to: destination.to_path_buf() });
 
     Ok(())
 }
 
-pub fn paste_file(path: &String) -> Result<(), Error>{
+fn copy_tree(source: &Path, destination: &Path, progress: &JobProgress) -> Result<(), Error> {
+    if std::path::absolute(destination)?.starts_with(std::path::absolute(source)?) {
+        return Err(Error::new(ErrorKind::InvalidInput, "can't copy a folder into itself"));
+    }
 
-    let cache_dir = util::get_cahce_fodler().unwrap();
+    copy_entry(source, destination, progress)
+}
+
+fn copy_entry(source: &Path, destination: &Path, progress: &JobProgress) -> Result<(), Error> {
+    progress.checkpoint()?;
+    let md = fs::symlink_metadata(source)?;
+
+    if md.file_type().is_symlink() {
+        // Recreate the link itself rather than copying whatever it points at
+        symlink(fs::read_link(source)?, destination)?;
+        progress.add_done(md.len(), 1);
+        return Ok(());
+    }
+
+    let file_type = md.file_type();
+    if file_type.is_fifo() || file_type.is_socket() || file_type.is_block_device() || file_type.is_char_device() {
+        // Reading a FIFO waits for a writer and a device never ends, so these are left out and counted instead
+        progress.skip();
+        progress.add_done(md.len(), 1);
+        return Ok(());
+    }
+
+    // Times and permissions go through the handle the copy was made with, since modes like 0o200 can't be opened again.
+    // Permissions are set last so a read-only folder can still be filled first
+    let copied = if md.is_dir() {
+        fs::create_dir(destination)?;
+
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_entry(&entry.path(), &destination.join(entry.file_name()), progress)?;
+        }
+        File::open(destination)?
+    } else {
+        copy_contents(source, destination, progress)?
+    };
+
+    copied.set_times(FileTimes::new().set_accessed(md.accessed()?).set_modified(md.modified()?))?;
+    copied.set_permissions(md.permissions())
+}
+
+fn copy_contents(source: &Path, destination: &Path, progress: &JobProgress) -> Result<File, Error> {
+    let mut reader = File::open(source)?;
+    // Never replace an entry that turned up since the conflict check. Overwrites trash the old entry first
+    let mut writer = OpenOptions::new().write(true).create_new(true).open(destination)?;
+
+    // Don't leave a half-written file behind when the copy fails or is cancelled
+    write_contents(&mut reader, &mut writer, progress).inspect_err(|_| {
+        let _ = fs::remove_file(destination);
+    })?;
+
+    Ok(writer)
+}
+
+fn write_contents(reader: &mut File, writer: &mut File, progress: &JobProgress) -> Result<(), Error> {
+    let mut buffer = vec![0; 1024 * 1024];
+
+    loop {
+        progress.checkpoint()?;
+
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
 
-    let files = util::get_files_in_directory(&cache_dir)?;
+        writer.write_all(&buffer[..read])?;
+        progress.add_done(read as u64, 0);
+    }
 
-    
-    let cached_file_path =files.first().unwrap();
-    let cached_file_name = util::strip_directory(&cached_file_path);
-    
-    let dest_path = format!("{}\\{}", path, cached_file_name);
-    
-    fs::copy(cached_file_path, dest_path)?;
+    progress.add_done(0, 1);
+    Ok(())
+}
 
-    //Remove pasted file from the cache
-    let _ = delete_file(cached_file_path);
-    
+/// Moves a file or folder, falling back to copy and delete when it crosses filesystems.
+pub fn move_path(source: &Path, destination: &Path, progress: &JobProgress) -> Result<(), Error> {
+    relocate(source, destination, progress)?;
+    journal::record(Change::Move { from: source.to_path_buf(), to: destination.to_path_buf() });
 
     Ok(())
 }
+
+/// Moves an entry without journaling it, for moves that are part of a bigger change like trashing.
+pub(crate) fn relocate(source: &Path, destination: &Path, progress: &JobProgress) -> Result<(), Error> {
+    progress.checkpoint()?;
+
+    match fs::rename(source, destination) {
+        Ok(_) => {
+            let (bytes, files) = util::measure(destination);
+            progress.add_done(bytes, files);
+            Ok(())
+        },
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            let skipped = progress.skipped();
+            copy_tree(source, destination, progress)?;
+
+            // Removing the source would lose whatever couldn't be copied along
+            if progress.skipped() > skipped {
+                return Err(Error::other(format!("copied, but {} was kept since it has entries that can't be copied", source.display())));
+            }
+            util::remove_path(source)
+        },
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::filesystem::util;
+use crate::Error;
+
+// Once the ranks add up to more than this, they're all scaled down and the least visited folders are forgotten, the same as zoxide
+const MAX_RANK: f64 = 10000.0;
+
+/// How often and how lately each folder was visited, for jumping to one with a few letters of its path.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Frecency {
+    pub directories: HashMap<String, Visits>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Visits {
+    pub rank: f64,
+    // Seconds since the epoch. Imported folders have 0, so their rank counts at the weight of a visit over a week old
+    pub last: u64,
+}
+
+impl Visits {
+    /// Weighs the rank by how recent the last visit was.
+    fn score(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last);
+
+        let factor = match age {
+            _ if age < 60 * 60 => 4.0,
+            _ if age < 24 * 60 * 60 => 2.0,
+            _ if age < 7 * 24 * 60 * 60 => 0.5,
+            _ => 0.25,
+        };
+
+        self.rank * factor
+    }
+}
+
+/// Where an existing database can be imported from.
+pub enum Source {
+    Zoxide,
+    Autojump,
+}
+
+/// Returns where visits are kept, `$XDG_DATA_HOME/rfe/frecency.json`.
+pub fn frecency_path() -> PathBuf {
+    util::xdg_data_home().join("rfe").join("frecency.json")
+}
+
+/// The current time the way visits record it.
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
+}
+
+/// Reads the recorded visits, none when nothing has been visited yet.
+pub fn load() -> Result<Frecency, Error> {
+    let contents = match fs::read_to_string(frecency_path()) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Frecency::default()),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(serde_json::from_str(&contents)?)
+}
+
+pub fn save(frecency: &Frecency) -> Result<(), Error> {
+    let path = frecency_path();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string(frecency)?)?;
+
+    Ok(())
+}
+
+impl Frecency {
+    pub fn visit(&mut self, path: &str, now: u64) {
+        self.add(path, 1.0, now);
+        self.age();
+    }
+
+    fn add(&mut self, path: &str, rank: f64, last: u64) {
+        let visits = self.directories.entry(path.to_string()).or_insert(Visits { rank: 0.0, last: 0 });
+
+        visits.rank += rank;
+        visits.last = visits.last.max(last);
+    }
+
+    fn age(&mut self) {
+        let total: f64 = self.directories.values().map(|visits| visits.rank).sum();
+        if total <= MAX_RANK {
+            return;
+        }
+
+        let factor = 0.9 * MAX_RANK / total;
+        for visits in self.directories.values_mut() {
+            visits.rank *= factor;
+        }
+        self.directories.retain(|_, visits| visits.rank >= 1.0);
+    }
+
+    /// Finds the best folder for `query`, other than `current`. Like zoxide, the words have to appear in the path in order, ignoring case,
+    /// and the last one in the folder's own name. Folders that no longer exist are skipped.
+    pub fn best(&self, query: &str, current: &str, now: u64) -> Option<&str> {
+        let words: Vec<String> = query.split_whitespace().map(|word| word.to_lowercase()).collect();
+        if words.is_empty() {
+            return None;
+        }
+
+        self.directories
+            .iter()
+            .filter(|(path, _)| path.as_str() != current && matches(&words, path))
+            .filter(|(path, _)| Path::new(path).is_dir())
+            .max_by(|a, b| a.1.score(now).total_cmp(&b.1.score(now)))
+            .map(|(path, _)| path.as_str())
+    }
+
+    /// Adds the folders from another tool's database to these, returning how many were read.
+    pub fn import(&mut self, source: Source) -> Result<usize, io::Error> {
+        let entries = match source {
+            Source::Zoxide => read_zoxide()?,
+            Source::Autojump => read_autojump()?,
+        };
+
+        for (path, rank) in &entries {
+            self.add(path, *rank, 0);
+        }
+        self.age();
+
+        Ok(entries.len())
+    }
+}
+
+fn matches(words: &[String], path: &str) -> bool {
+    let path = path.to_lowercase();
+    let mut rest = path.as_str();
+
+    for word in words {
+        match rest.find(word.as_str()) {
+            Some(start) => rest = &rest[start + word.len()..],
+            None => return false,
+        }
+    }
+
+    // The last word has to be in the last component, so `z src` doesn't pick a folder inside some src
+    let name = path.rsplit('/').next().unwrap_or_default();
+    words.last().is_some_and(|word| name.contains(word.as_str()))
+}
+
+// `zoxide query --list --score` prints each folder as `  12.5 /path`
+fn read_zoxide() -> Result<Vec<(String, f64)>, io::Error> {
+    let output = Command::new("zoxide")
+        .args(["query", "--list", "--score"])
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("could not run zoxide: {}", e)))?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!("zoxide failed: {}", message)));
+    }
+
+    Ok(parse_scores(&String::from_utf8_lossy(&output.stdout), char::is_whitespace))
+}
+
+// autojump keeps `12.5\t/path` lines in `$XDG_DATA_HOME/autojump/autojump.txt`
+fn read_autojump() -> Result<Vec<(String, f64)>, io::Error> {
+    let contents = fs::read_to_string(util::xdg_data_home().join("autojump").join("autojump.txt"))?;
+
+    Ok(parse_scores(&contents, |c| c == '\t'))
+}
+
+fn parse_scores(text: &str, separator: fn(char) -> bool) -> Vec<(String, f64)> {
+    text.lines()
+        .filter_map(|line| {
+            let (score, path) = line.trim_start().split_once(separator)?;
+            Some((path.trim_start().to_string(), score.parse().ok()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000_000_000;
+
+    fn words(query: &str) -> Vec<String> {
+        query.split_whitespace().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn zoxide_and_autojump_lines() {
+        let zoxide = "  12.5 /home/me/src\n   3 /tmp/with space\nnot a score\n\n";
+        assert_eq!(parse_scores(zoxide, char::is_whitespace), [("/home/me/src".to_string(), 12.5), ("/tmp/with space".to_string(), 3.0)]);
+
+        let autojump = "40.0\t/home/me/src\n2\t/tmp/with space\nbroken line\n";
+        assert_eq!(parse_scores(autojump, |c| c == '\t'), [("/home/me/src".to_string(), 40.0), ("/tmp/with space".to_string(), 2.0)]);
+    }
+
+    #[test]
+    fn words_in_order_and_the_last_in_the_name() {
+        assert!(matches(&words("me src"), "/home/me/src"));
+        assert!(matches(&words("src"), "/home/me/SRC"));
+        assert!(!matches(&words("src me"), "/home/me/src"));
+        // `src` is in the path, but not in the folder's own name
+        assert!(!matches(&words("src"), "/home/src/project"));
+        assert!(matches(&words("src proj"), "/home/src/project"));
+    }
+
+    #[test]
+    fn recent_visits_weigh_more() {
+        let visits = Visits { rank: 2.0, last: NOW };
+        assert_eq!(visits.score(NOW), 8.0);
+        assert_eq!(Visits { last: NOW - 2 * 60 * 60, ..visits }.score(NOW), 4.0);
+        assert_eq!(Visits { last: 0, ..visits }.score(NOW), 0.5);
+    }
+
+    #[test]
+    fn best_skips_the_current_folder_and_folders_that_are_gone() {
+        let root = std::env::temp_dir().join(format!("rfe-frecency-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for folder in ["src", "other/src", "src/deep"] {
+            fs::create_dir_all(root.join(folder)).unwrap();
+        }
+        let path = |folder: &str| root.join(folder).to_string_lossy().into_owned();
+
+        let mut frecency = Frecency::default();
+        frecency.add(&path("src"), 5.0, NOW);
+        frecency.add(&path("other/src"), 1.0, NOW);
+        frecency.add(&path("src/deep"), 50.0, NOW);
+        frecency.add(&path("gone/src"), 100.0, NOW);
+
+        // src/deep ranks highest but its name doesn't have `src`, and gone/src isn't there
+        assert_eq!(frecency.best("src", "/", NOW), Some(path("src").as_str()));
+        assert_eq!(frecency.best("src", &path("src"), NOW), Some(path("other/src").as_str()));
+        assert_eq!(frecency.best("other src", "/", NOW), Some(path("other/src").as_str()));
+        assert_eq!(frecency.best("  ", "/", NOW), None);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
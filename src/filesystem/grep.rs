@@ -0,0 +1,128 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use ignore::WalkBuilder;
+use regex::Regex;
+
+use crate::jobs::JobProgress;
+
+const MAX_RESULTS: usize = 1000;
+const MAX_SNIPPET: usize = 200;
+// How much of a file is checked for a NUL byte to decide it's binary, the same amount git checks
+const BINARY_CHECK: usize = 8000;
+
+/// A line that matched a content search.
+pub struct GrepMatch {
+    pub path: String,
+    pub line: u64,
+    pub snippet: String,
+    // Byte range of the first match within the snippet
+    pub matched: (usize, usize),
+}
+
+/// Builds the pattern to search for. Literal text is escaped, and either ignores case unless it has an uppercase letter.
+pub fn pattern(text: &str, regex: bool) -> Result<Regex, Error> {
+    let pattern = if regex { text.to_string() } else { regex::escape(text) };
+    let pattern = match text.chars().any(|c| c.is_uppercase()) {
+        true => pattern,
+        false => format!("(?i){}", pattern),
+    };
+
+    // The last line of a regex error says what's wrong, the rest points at where
+    Regex::new(&pattern).map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string().lines().last().unwrap_or_default().trim().to_string()))
+}
+
+/// Searches every text file under `root` that isn't ignored by a `.gitignore`, adding matches to `results` as they're found.
+pub fn search(root: &str, pattern: &Regex, results: &Mutex<Vec<GrepMatch>>, progress: &JobProgress) -> Result<(), Error> {
+    // List the files first so progress can be shown by bytes
+    let mut files: Vec<(PathBuf, u64)> = Vec::new();
+    for entry in WalkBuilder::new(root).require_git(false).build() {
+        progress.checkpoint()?;
+
+        let entry = match entry {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+            continue;
+        }
+
+        let size = entry.metadata().map(|md| md.len()).unwrap_or(0);
+        progress.add_total(size, 1);
+        files.push((entry.into_path(), size));
+    }
+
+    for (path, size) in files {
+        progress.checkpoint()?;
+
+        // Unreadable files are skipped, the same as binary ones
+        let _ = search_file(&path, pattern, results);
+        progress.add_done(size, 1);
+
+        if results.lock().unwrap_or_else(|e| e.into_inner()).len() >= MAX_RESULTS {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn search_file(path: &PathBuf, pattern: &Regex, results: &Mutex<Vec<GrepMatch>>) -> Result<(), Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let start = reader.fill_buf()?;
+    if start[..start.len().min(BINARY_CHECK)].contains(&0) {
+        return Ok(());
+    }
+
+    let mut line = Vec::new();
+    let mut number = 0;
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        number += 1;
+
+        // Lines that aren't UTF-8 are still searched, with the bad bytes replaced
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\n', '\r']);
+
+        if let Some(found) = pattern.find(text) {
+            let snippet = snippet(text, found.start(), found.end());
+            let mut results = results.lock().unwrap_or_else(|e| e.into_inner());
+
+            results.push(GrepMatch {
+                path: path.to_string_lossy().into_owned(),
+                line: number,
+                snippet: snippet.0,
+                matched: snippet.1,
+            });
+            if results.len() >= MAX_RESULTS {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Trims the line's indentation and keeps at most MAX_SNIPPET bytes around the match
+fn snippet(text: &str, start: usize, end: usize) -> (String, (usize, usize)) {
+    let indent = text.len() - text.trim_start().len();
+    let mut from = indent.min(start);
+    if end - from > MAX_SNIPPET {
+        from = start;
+    }
+    let mut to = (from + MAX_SNIPPET).max(end).min(text.len());
+
+    while !text.is_char_boundary(from) {
+        from -= 1;
+    }
+    while !text.is_char_boundary(to) {
+        to += 1;
+    }
+
+    (text[from..to].to_string(), (start - from, end - from))
+}
@@ -0,0 +1,242 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::filesystem::util;
+use crate::config::IndexConfig;
+use crate::filesystem::query::Query;
+use crate::fuzzy;
+use crate::jobs::JobProgress;
+use crate::Error;
+
+/// Every file and folder under the configured roots, saved between runs so startup doesn't have to walk the whole tree.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Index {
+    pub roots: Vec<String>,
+    // What the index was built with, since unchanged folders can only be reused under the same rules
+    pub config: Option<IndexConfig>,
+    // Keyed by full path, so files that share a name are all kept
+    pub entries: Vec<IndexEntry>,
+    // The mtime each folder had when it was last read. A folder's mtime changes whenever an entry is added, removed or renamed
+    pub directories: HashMap<String, SystemTime>,
+}
+
+/// What searches can filter on. Files edited in place don't change their folder's mtime,
+/// so their size and mtime are only as fresh as the last time their folder was read.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexEntry {
+    pub path: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+// The entries and folders directly inside one folder of a previous index
+#[derive(Default)]
+struct Known<'a> {
+    entries: Vec<&'a IndexEntry>,
+    directories: Vec<&'a String>,
+}
+
+/// Returns where the index is kept, `$XDG_CACHE_HOME/rfe/index.json`.
+pub fn index_path() -> PathBuf {
+    util::xdg_cache_home().join("rfe").join("index.json")
+}
+
+pub fn load() -> Result<Index, Error> {
+    let contents = fs::read_to_string(index_path())?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+pub fn save(index: &Index) -> Result<(), Error> {
+    let path = index_path();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string(index)?)?;
+
+    Ok(())
+}
+
+/// Brings an index up to date, only reading folders whose mtime has changed since `previous` was built.
+/// With `rebuild`, or when the roots or rules have changed, every folder is read again and `previous` is only used to estimate progress.
+pub fn update(config: &IndexConfig, previous: &Index, rebuild: bool, progress: &JobProgress) -> Result<Index, std::io::Error> {
+    let roots = config.roots
+        .iter()
+        .map(|root| Ok(std::path::absolute(root)?.to_string_lossy().into_owned()))
+        .collect::<Result<Vec<String>, std::io::Error>>()?;
+    let reuse = !rebuild && previous.roots == roots && previous.config.as_ref() == Some(config);
+
+    let exclude: Vec<glob::Pattern> = config.exclude
+        .iter()
+        .map(|pattern| glob::Pattern::new(pattern).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("bad exclude {}: {}", pattern, e))))
+        .collect::<Result<_, _>>()?;
+    let rules = Rules { config, exclude };
+
+    // Group what's already known by folder, so an unchanged folder can be copied over without reading it
+    let mut known: HashMap<&str, Known> = HashMap::new();
+    if reuse {
+        for entry in &previous.entries {
+            known.entry(parent(&entry.path)).or_default().entries.push(entry);
+        }
+        for directory in previous.directories.keys() {
+            known.entry(parent(directory)).or_default().directories.push(directory);
+        }
+    }
+
+    progress.add_total(0, previous.directories.len().max(1) as u64);
+
+    let mut index = Index {
+        roots: roots.clone(),
+        config: Some(config.clone()),
+        ..Default::default()
+    };
+    // Each folder with how far below its root it is
+    let mut pending: Vec<(String, usize)> = roots.into_iter().rev().map(|root| (root, 0)).collect();
+    // Where followed links have already led, so a link back up the tree can't loop forever
+    let mut visited = HashSet::new();
+
+    while let Some((directory, depth)) = pending.pop() {
+        progress.checkpoint()?;
+
+        let md = match fs::metadata(&directory) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        // Roots can overlap, or be reached again through a link
+        if !visited.insert((md.dev(), md.ino())) {
+            continue;
+        }
+        let modified = md.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let unchanged = reuse && previous.directories.get(&directory) == Some(&modified);
+        match known.get(directory.as_str()) {
+            Some(known) if unchanged => {
+                index.entries.extend(known.entries.iter().map(|entry| (*entry).clone()));
+                pending.extend(known.directories.iter().map(|directory| ((*directory).clone(), depth + 1)));
+            },
+            _ if unchanged => {},
+            _ => read_directory(&directory, depth, &rules, &mut index, &mut pending),
+        }
+
+        index.directories.insert(directory, modified);
+        progress.add_done(0, 1);
+    }
+
+    // A folder's entry may have been copied from its unchanged parent, but its own mtime was just read
+    for entry in index.entries.iter_mut().filter(|entry| entry.is_dir) {
+        if let Some(modified) = index.directories.get(&entry.path) {
+            entry.modified = *modified;
+        }
+    }
+
+    Ok(index)
+}
+
+// The index settings with the exclude globs compiled
+struct Rules<'a> {
+    config: &'a IndexConfig,
+    exclude: Vec<glob::Pattern>,
+}
+
+impl Rules<'_> {
+    fn skips(&self, name: &str, path: &str) -> bool {
+        (!self.config.hidden && name.starts_with('.'))
+            || self.exclude.iter().any(|pattern| pattern.matches(name) || pattern.matches(path))
+    }
+}
+
+fn read_directory(directory: &str, depth: usize, rules: &Rules, index: &mut Index, pending: &mut Vec<(String, usize)>) {
+    if rules.config.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+        return;
+    }
+
+    let entries = match fs::read_dir(directory) {
+        Ok(data) => data,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path().to_string_lossy().into_owned();
+        if rules.skips(&entry.file_name().to_string_lossy(), &path) {
+            continue;
+        }
+
+        let md = match entry.metadata() {
+            Ok(md) if md.file_type().is_symlink() && rules.config.follow_symlinks => fs::metadata(&path),
+            data => data,
+        };
+        let md = match md {
+            Ok(md) if md.is_dir() || md.is_file() => md,
+            _ => continue,
+        };
+
+        if md.is_dir() {
+            pending.push((path.clone(), depth + 1));
+        }
+        index.entries.push(IndexEntry {
+            path,
+            is_dir: md.is_dir(),
+            size: md.len(),
+            modified: md.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+    }
+}
+
+const MAX_RESULTS: usize = 500;
+
+/// A file that matched a search, with the matched char positions in its relative path.
+pub struct Match {
+    pub path: String,
+    pub score: i64,
+    pub positions: Vec<usize>,
+}
+
+/// Runs a parsed query over every entry, best fuzzy matches first. See `query::Query` for what it can contain.
+pub fn search(index: &Index, query: &Query, progress: &JobProgress) -> Result<Vec<Match>, std::io::Error> {
+    let now = SystemTime::now();
+    let mut scratch = fuzzy::Scratch::default();
+    let mut matches = Vec::new();
+
+    for (i, entry) in index.entries.iter().enumerate() {
+        // Checked now and then, since a newer search replaces this one on every keystroke
+        if i % 4096 == 0 {
+            progress.checkpoint()?;
+        }
+        if let Some((score, positions)) = query.matches(entry, relative_path(index, &entry.path), now, &mut scratch) {
+            matches.push(Match { path: entry.path.clone(), score, positions });
+        }
+    }
+
+    // Shorter paths win ties, since there's less in them that didn't match
+    matches.sort_by(|a, b| b.score.cmp(&a.score).then(a.path.len().cmp(&b.path.len())));
+    matches.truncate(MAX_RESULTS);
+
+    Ok(matches)
+}
+
+/// Shows a path relative to the root it was found under, which is enough to tell files with the same name apart.
+/// With more than one root the root's own name is kept, so it's clear which one a path is in.
+pub fn relative_path<'a>(index: &Index, path: &'a str) -> &'a str {
+    let root = index.roots
+        .iter()
+        .filter(|root| Path::new(path).starts_with(root.as_str()))
+        .max_by_key(|root| root.len());
+
+    let prefix = match root {
+        Some(root) if index.roots.len() > 1 => parent(root),
+        Some(root) => root.as_str(),
+        None => return path,
+    };
+
+    path.strip_prefix(prefix).map(|relative| relative.trim_start_matches('/')).unwrap_or(path)
+}
+
+fn parent(path: &str) -> &str {
+    Path::new(path).parent().and_then(|parent| parent.to_str()).unwrap_or_default()
+}
@@ -0,0 +1,441 @@
+use std::cell::{Cell, RefCell};
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::filesystem::permissions::{self, Attributes};
+use crate::filesystem::{explorer, trash, util};
+use crate::jobs::JobProgress;
+
+const MAX_ENTRIES: usize = 500;
+
+/// A single filesystem mutation that can be reversed and replayed.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Change {
+    Rename { from: PathBuf, to: PathBuf },
+    Move { from: PathBuf, to: PathBuf },
+    Copy { from: PathBuf, to: PathBuf },
+    Create { path: PathBuf, directory: bool },
+    Trash { original: PathBuf, trashed: PathBuf },
+    Restore { trashed: PathBuf, original: PathBuf },
+    Link { target: PathBuf, path: PathBuf, hard: bool },
+    Permissions { path: PathBuf, from: Attributes, to: Attributes },
+}
+
+impl Change {
+    /// Makes every path absolute, so the journal still works after restarting somewhere else
+    fn absolute(self) -> Change {
+        let absolute = |path: PathBuf| std::path::absolute(&path).unwrap_or(path);
+
+        match self {
+            Change::Rename { from, to } => Change::Rename { from: absolute(from), to: absolute(to) },
+            Change::Move { from, to } => Change::Move { from: absolute(from), to: absolute(to) },
+            Change::Copy { from, to } => Change::Copy { from: absolute(from), to: absolute(to) },
+            Change::Create { path, directory } => Change::Create { path: absolute(path), directory },
+            Change::Trash { original, trashed } => Change::Trash { original: absolute(original), trashed },
+            Change::Restore { trashed, original } => Change::Restore { trashed, original: absolute(original) },
+            Change::Link { target, path, hard } => Change::Link { target: absolute(target), path: absolute(path), hard },
+            Change::Permissions { path, from, to } => Change::Permissions { path: absolute(path), from, to },
+        }
+    }
+
+    fn describe(&self) -> String {
+        let name = |path: &Path| util::strip_directory(&path.to_string_lossy());
+
+        match self {
+            Change::Rename { from, to } => format!("Rename {} to {}", name(from), name(to)),
+            Change::Move { from, to } => format!("Move {} to {}", name(from), to.display()),
+            Change::Copy { from, to } => format!("Copy {} to {}", name(from), to.display()),
+            Change::Create { path, .. } => format!("Create {}", name(path)),
+            Change::Trash { original, .. } => format!("Trash {}", name(original)),
+            Change::Restore { original, .. } => format!("Restore {}", name(original)),
+            Change::Link { path, .. } => format!("Link {}", name(path)),
+            Change::Permissions { path, .. } => format!("Change permissions of {}", name(path)),
+        }
+    }
+
+    fn undo(&mut self, progress: &JobProgress) -> Result<(), Error> {
+        match self {
+            Change::Rename { from, to } | Change::Move { from, to } => move_back(to, from, progress),
+            // Anything undo takes away goes to the trash, never straight to deletion
+            Change::Copy { to: path, .. } | Change::Create { path, .. } | Change::Link { path, .. } => trash::trash_path(&path.to_string_lossy(), progress).map(|_| ()),
+            Change::Trash { original, trashed } => trash::restore(&trashed_entry(original, trashed), progress),
+            Change::Restore { trashed, original } => {
+                *trashed = trash::trash_path(&original.to_string_lossy(), progress)?;
+                Ok(())
+            },
+            Change::Permissions { path, from, to } => permissions::write_attributes(path, &fs::symlink_metadata(&*path)?, *to, *from),
+        }
+    }
+
+    fn redo(&mut self, progress: &JobProgress) -> Result<(), Error> {
+        match self {
+            Change::Rename { from, to } | Change::Move { from, to } => move_back(from, to, progress),
+            Change::Copy { from, to } => explorer::copy_recursive(from, to, progress),
+            Change::Create { path, directory: true } => fs::create_dir(path),
+            Change::Create { path, directory: false } => File::create_new(path).map(|_| ()),
+            Change::Link { target, path, hard: true } => fs::hard_link(target, path),
+            Change::Link { target, path, hard: false } => std::os::unix::fs::symlink(target, path),
+            Change::Permissions { path, from, to } => permissions::write_attributes(path, &fs::symlink_metadata(&*path)?, *from, *to),
+            Change::Trash { original, trashed } => {
+                // The entry may land under a different name in the trash this time
+                *trashed = trash::trash_path(&original.to_string_lossy(), progress)?;
+                Ok(())
+            },
+            Change::Restore { trashed, original } => trash::restore(&trashed_entry(original, trashed), progress),
+        }
+    }
+}
+
+// The trash entry an original path was trashed as, enough to restore it
+fn trashed_entry(original: &Path, trashed: &Path) -> trash::TrashEntry {
+    trash::TrashEntry {
+        name: util::strip_directory(&trashed.to_string_lossy()),
+        original_path: original.to_string_lossy().into_owned(),
+        deletion_date: String::new(),
+        trashed_path: trashed.to_path_buf(),
+    }
+}
+
+fn move_back(from: &Path, to: &Path, progress: &JobProgress) -> Result<(), Error> {
+    if fs::symlink_metadata(to).is_ok() {
+        return Err(Error::new(ErrorKind::AlreadyExists, format!("{} already exists", to.display())));
+    }
+
+    explorer::move_path(from, to, progress)
+}
+
+/// One user action, which may have touched several entries.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Entry {
+    pub description: String,
+    pub time: String,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Journal {
+    pub undo: Vec<Entry>,
+    pub redo: Vec<Entry>,
+}
+
+static JOURNAL: Mutex<Option<Journal>> = Mutex::new(None);
+
+thread_local! {
+    // Changes made inside `batch` are collected here and saved as a single entry
+    static BATCH: RefCell<Option<Vec<Change>>> = const { RefCell::new(None) };
+    // Set while undoing or redoing so those changes aren't journaled again
+    static PAUSED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Returns where the journal is kept, `$XDG_STATE_HOME/rfe/journal.json`.
+pub fn journal_path() -> PathBuf {
+    util::xdg_state_home().join("rfe").join("journal.json")
+}
+
+fn with_journal<R>(f: impl FnOnce(&mut Journal) -> R) -> R {
+    let mut guard = JOURNAL.lock().unwrap_or_else(|e| e.into_inner());
+
+    let journal = guard.get_or_insert_with(|| {
+        fs::read_to_string(journal_path())
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    });
+
+    f(journal)
+}
+
+fn save(journal: &Journal) {
+    let path = journal_path();
+
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Ok(contents) = serde_json::to_string(journal) {
+        let _ = fs::write(path, contents);
+    }
+}
+
+fn push(entry: Entry) {
+    with_journal(|journal| {
+        journal.undo.push(entry);
+        journal.redo.clear();
+
+        if journal.undo.len() > MAX_ENTRIES {
+            journal.undo.remove(0);
+        }
+        save(journal);
+    });
+}
+
+/// Records a change made through `explorer`.
+pub fn record(change: Change) {
+    if PAUSED.with(|paused| paused.get()) {
+        return;
+    }
+    let change = change.absolute();
+
+    let batched = BATCH.with(|batch| match batch.borrow_mut().as_mut() {
+        Some(changes) => {
+            changes.push(change.clone());
+            true
+        },
+        None => false,
+    });
+
+    if !batched {
+        push(Entry {
+            description: change.describe(),
+            time: Local::now().format("%d/%m/%Y %T").to_string(),
+            changes: vec![change],
+        });
+    }
+}
+
+/// Runs `work` and journals every change it makes as one entry, so it's undone in one step.
+/// A batch started inside another one just adds to the outer entry.
+pub fn batch<R>(description: &str, work: impl FnOnce() -> R) -> R {
+    if BATCH.with(|batch| batch.borrow().is_some()) {
+        return work();
+    }
+
+    BATCH.with(|batch| *batch.borrow_mut() = Some(Vec::new()));
+    let res = work();
+    let changes = BATCH.with(|batch| batch.borrow_mut().take()).unwrap_or_default();
+
+    // Whatever finished before a failure or cancel is still journaled
+    if !changes.is_empty() {
+        push(Entry {
+            description: description.to_string(),
+            time: Local::now().format("%d/%m/%Y %T").to_string(),
+            changes,
+        });
+    }
+
+    res
+}
+
+/// The description of the entry undo would reverse next, if there is one.
+pub fn next_undo() -> Option<String> {
+    with_journal(|journal| journal.undo.last().map(|entry| entry.description.clone()))
+}
+
+/// The description of the entry redo would replay next, if there is one.
+pub fn next_redo() -> Option<String> {
+    with_journal(|journal| journal.redo.last().map(|entry| entry.description.clone()))
+}
+
+/// Reverses the most recent entry, checking `progress` between changes. Returns its description.
+pub fn undo(progress: &JobProgress) -> Result<String, Error> {
+    let mut entry = with_journal(|journal| journal.undo.pop()).ok_or_else(|| Error::new(ErrorKind::NotFound, "nothing to undo"))?;
+
+    PAUSED.with(|paused| paused.set(true));
+    let mut undone = Vec::new();
+    let mut res = Ok(());
+
+    while let Some(mut change) = entry.changes.pop() {
+        match progress.checkpoint().and_then(|_| change.undo(progress)) {
+            Ok(_) => undone.insert(0, change),
+            Err(e) => {
+                entry.changes.push(change);
+                res = Err(e);
+                break;
+            },
+        }
+    }
+    PAUSED.with(|paused| paused.set(false));
+
+    let Entry { description, time, changes } = entry;
+    with_journal(|journal| {
+        // Anything that couldn't be undone stays on the undo stack
+        if !changes.is_empty() {
+            journal.undo.push(Entry { description: description.clone(), time: time.clone(), changes });
+        }
+        if !undone.is_empty() {
+            journal.redo.push(Entry { description: description.clone(), time, changes: undone });
+        }
+        save(journal);
+    });
+
+    res.map(|_| description)
+}
+
+/// Replays the most recently undone entry, checking `progress` between changes. Returns its description.
+pub fn redo(progress: &JobProgress) -> Result<String, Error> {
+    let mut entry = with_journal(|journal| journal.redo.pop()).ok_or_else(|| Error::new(ErrorKind::NotFound, "nothing to redo"))?;
+
+    PAUSED.with(|paused| paused.set(true));
+    let mut redone = Vec::new();
+    let mut res = Ok(());
+
+    while !entry.changes.is_empty() {
+        let mut change = entry.changes.remove(0);
+
+        match progress.checkpoint().and_then(|_| change.redo(progress)) {
+            Ok(_) => redone.push(change),
+            Err(e) => {
+                entry.changes.insert(0, change);
+                res = Err(e);
+                break;
+            },
+        }
+    }
+    PAUSED.with(|paused| paused.set(false));
+
+    let Entry { description, time, changes } = entry;
+    with_journal(|journal| {
+        if !changes.is_empty() {
+            journal.redo.push(Entry { description: description.clone(), time: time.clone(), changes });
+        }
+        if !redone.is_empty() {
+            journal.undo.push(Entry { description: description.clone(), time, changes: redone });
+        }
+        save(journal);
+    });
+
+    res.map(|_| description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::util::TestHome;
+
+    // A fresh home, with the journal read again from it rather than kept from another test
+    fn home(test: &str) -> TestHome {
+        let home = TestHome::new(test);
+        *JOURNAL.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        home
+    }
+
+    fn stacks() -> (Vec<usize>, Vec<usize>) {
+        with_journal(|journal| {
+            let sizes = |entries: &[Entry]| entries.iter().map(|entry| entry.changes.len()).collect();
+            (sizes(&journal.undo), sizes(&journal.redo))
+        })
+    }
+
+    #[test]
+    fn rename_undo_and_redo() {
+        let home = home("journal-rename");
+        let (a, b) = (home.path.join("a"), home.path.join("b"));
+        fs::write(&a, "").unwrap();
+
+        explorer::rename_file(&a, &b).unwrap();
+        assert_eq!(next_undo().as_deref(), Some("Rename a to b"));
+
+        assert_eq!(undo(&JobProgress::default()).unwrap(), "Rename a to b");
+        assert!(a.exists() && !b.exists());
+        assert_eq!(next_undo(), None);
+        assert_eq!(next_redo().as_deref(), Some("Rename a to b"));
+
+        redo(&JobProgress::default()).unwrap();
+        assert!(!a.exists() && b.exists());
+        assert_eq!(stacks(), (vec![1], vec![]));
+    }
+
+    #[test]
+    fn create_is_undone_into_the_trash() {
+        let home = home("journal-create");
+        let path = explorer::make_file(&home.path.to_string_lossy().into_owned(), &"new/file.txt".to_string()).unwrap();
+        // The missing folder and the file are one entry
+        assert_eq!(stacks(), (vec![2], vec![]));
+
+        undo(&JobProgress::default()).unwrap();
+        assert!(!home.path.join("new").exists());
+        assert_eq!(trash::list_trash().unwrap().0.len(), 2);
+
+        redo(&JobProgress::default()).unwrap();
+        assert!(path.is_file());
+    }
+
+    #[test]
+    fn trash_undo_restores_and_redo_trashes_again() {
+        let home = home("journal-trash");
+        let path = home.path.join("notes.txt");
+        fs::write(&path, "keep me").unwrap();
+
+        explorer::delete_file(&path.to_string_lossy(), &JobProgress::default()).unwrap();
+        assert!(!path.exists());
+
+        undo(&JobProgress::default()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep me");
+        assert_eq!(trash::list_trash().unwrap().0.len(), 0);
+
+        redo(&JobProgress::default()).unwrap();
+        assert!(!path.exists());
+        assert_eq!(trash::list_trash().unwrap().0.len(), 1);
+
+        // The redo put it back under its trash name, so a second undo still finds it
+        undo(&JobProgress::default()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep me");
+    }
+
+    #[test]
+    fn nested_batches_make_one_entry() {
+        let home = home("journal-batch");
+        let path = |name: &str| home.path.join(name);
+        for name in ["a", "c"] {
+            fs::write(path(name), "").unwrap();
+        }
+
+        batch("Outer", || {
+            explorer::rename_file(&path("a"), &path("b")).unwrap();
+            batch("Inner", || explorer::rename_file(&path("c"), &path("d")).unwrap());
+        });
+
+        assert_eq!(next_undo().as_deref(), Some("Outer"));
+        assert_eq!(stacks(), (vec![2], vec![]));
+    }
+
+    #[test]
+    fn failure_partway_splits_the_entry() {
+        let home = home("journal-partial");
+        let path = |name: &str| home.path.join(name);
+        for name in ["a", "c"] {
+            fs::write(path(name), "").unwrap();
+        }
+        batch("Both", || {
+            explorer::rename_file(&path("a"), &path("b")).unwrap();
+            explorer::rename_file(&path("c"), &path("d")).unwrap();
+        });
+
+        // Undo runs backwards, so c comes back and then a is blocked by a new file in its place
+        fs::write(path("a"), "").unwrap();
+        let e = undo(&JobProgress::default()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::AlreadyExists);
+        assert!(path("c").exists() && !path("d").exists());
+        assert!(path("b").exists());
+        assert_eq!(stacks(), (vec![1], vec![1]));
+
+        // Once it's out of the way the rest can be undone, and redo replays both halves
+        fs::remove_file(path("a")).unwrap();
+        undo(&JobProgress::default()).unwrap();
+        assert!(path("a").exists() && !path("b").exists());
+        assert_eq!(stacks(), (vec![], vec![1, 1]));
+
+        redo(&JobProgress::default()).unwrap();
+        redo(&JobProgress::default()).unwrap();
+        assert!(path("b").exists() && path("d").exists());
+        assert_eq!(stacks(), (vec![1, 1], vec![]));
+    }
+
+    #[test]
+    fn a_new_change_clears_redo() {
+        let home = home("journal-clears-redo");
+        let path = |name: &str| home.path.join(name);
+        fs::write(path("a"), "").unwrap();
+
+        explorer::rename_file(&path("a"), &path("b")).unwrap();
+        undo(&JobProgress::default()).unwrap();
+        explorer::rename_file(&path("a"), &path("c")).unwrap();
+
+        assert_eq!(next_redo(), None);
+        assert_eq!(redo(&JobProgress::default()).unwrap_err().kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,212 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+
+use crate::filesystem::{permissions, util};
+
+/// One row of the directory table, read once so sorting doesn't hit the disk again.
+#[derive(Clone)]
+pub struct Entry {
+    pub path: String,
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: SystemTime,
+    pub mode: u32,
+    pub permissions: String,
+    pub kind: String,
+}
+
+impl Entry {
+    pub fn read(path: String) -> Entry {
+        let name = util::strip_directory(&path);
+        let link = fs::symlink_metadata(&path);
+        // Links sort and show as what they point at, falling back to the link itself when broken
+        let md = fs::metadata(&path).or_else(|_| fs::symlink_metadata(&path));
+
+        let kind = match (&link, &md) {
+            (Ok(link), _) if link.file_type().is_symlink() => "Link".to_string(),
+            (_, Ok(md)) if md.is_dir() => "Folder".to_string(),
+            _ => match Path::new(&name).extension() {
+                Some(extension) => extension.to_string_lossy().to_uppercase(),
+                None => "File".to_string(),
+            },
+        };
+
+        Entry {
+            is_dir: md.as_ref().is_ok_and(|md| md.is_dir()),
+            size: md.as_ref().map(|md| md.len()).unwrap_or(0),
+            modified: md.as_ref().ok().and_then(|md| md.modified().ok()).unwrap_or(SystemTime::UNIX_EPOCH),
+            mode: link.as_ref().map(|md| md.mode() & 0o7777).unwrap_or(0),
+            permissions: link.as_ref().map(permissions::mode_string).unwrap_or_default(),
+            kind,
+            name,
+            path,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Name,
+    Size,
+    Modified,
+    Permissions,
+    Type,
+}
+
+impl SortKey {
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::Name => "Name",
+            SortKey::Size => "Size",
+            SortKey::Modified => "Modified",
+            SortKey::Permissions => "Permissions",
+            SortKey::Type => "Type",
+        }
+    }
+}
+
+/// The column the listing is sorted by and in which direction. Folders always come first.
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Sort {
+        Sort {
+            key: SortKey::Name,
+            descending: false,
+        }
+    }
+}
+
+impl Sort {
+    /// Sorts by `key`, or flips the direction when already sorted by it.
+    pub fn select(&mut self, key: SortKey) {
+        if self.key == key {
+            self.descending = !self.descending;
+        } else {
+            self.key = key;
+            self.descending = false;
+        }
+    }
+}
+
+/// Reads every entry of a folder. Entries that vanish while it's read are left out, and names that aren't UTF-8 are shown lossily.
+pub fn read_entries(directory: &String) -> Result<Vec<Entry>, std::io::Error> {
+    Ok(fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| Entry::read(entry.path().to_string_lossy().into_owned()))
+        .collect())
+}
+
+pub fn sort_entries(entries: &mut [Entry], sort: Sort) {
+    entries.sort_by(|a, b| {
+        let order = match sort.key {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+            SortKey::Permissions => a.mode.cmp(&b.mode),
+            SortKey::Type => a.kind.to_lowercase().cmp(&b.kind.to_lowercase()),
+        }
+        .then_with(|| natural_cmp(&a.name, &b.name));
+
+        let order = if sort.descending { order.reverse() } else { order };
+        b.is_dir.cmp(&a.is_dir).then(order)
+    });
+}
+
+/// Compares names the way people read them, so `file2` comes before `file10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    // Case and leading zeros only decide between names that are otherwise the same
+    let mut case = Ordering::Equal;
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return case,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+
+                // Compare by length first once leading zeros are gone, so numbers of any size work
+                let order = x.trim_start_matches('0').len().cmp(&y.trim_start_matches('0').len())
+                    .then_with(|| x.trim_start_matches('0').cmp(y.trim_start_matches('0')));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                case = case.then(x.len().cmp(&y.len()));
+            },
+            (Some(x), Some(y)) => {
+                let order = x.to_lowercase().cmp(y.to_lowercase());
+                if order != Ordering::Equal {
+                    return order;
+                }
+                case = case.then(x.cmp(&y));
+                a.next();
+                b.next();
+            },
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut number = String::new();
+
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        number.push(*c);
+        chars.next();
+    }
+
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        names.sort_by(|a, b| natural_cmp(a, b));
+        names
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(sorted(&["file10", "file2", "file1"]), ["file1", "file2", "file10"]);
+        assert_eq!(sorted(&["v1.10", "v1.9", "v1.1"]), ["v1.1", "v1.9", "v1.10"]);
+        // Longer than any integer type
+        assert_eq!(natural_cmp("a99999999999999999999999", "a100000000000000000000000"), Ordering::Less);
+    }
+
+    #[test]
+    fn case_ignored_until_the_names_are_otherwise_equal() {
+        assert_eq!(sorted(&["b", "A", "a", "B"]), ["A", "a", "B", "b"]);
+        assert_eq!(natural_cmp("Apple", "banana"), Ordering::Less);
+    }
+
+    #[test]
+    fn leading_zeros_only_break_ties() {
+        assert_eq!(natural_cmp("file01", "file1"), Ordering::Greater);
+        assert_eq!(natural_cmp("file01", "file2"), Ordering::Less);
+        assert_eq!(natural_cmp("file007", "file007"), Ordering::Equal);
+    }
+
+    #[test]
+    fn prefixes_come_first() {
+        assert_eq!(natural_cmp("file", "file1"), Ordering::Less);
+        assert_eq!(natural_cmp("", "a"), Ordering::Less);
+    }
+}
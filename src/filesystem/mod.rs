@@ -1,2 +1,13 @@
+pub mod bookmarks;
+pub mod conflict;
 pub mod explorer;
+pub mod frecency;
+pub mod grep;
+pub mod index;
+pub mod journal;
+pub mod listing;
+pub mod permissions;
+pub mod query;
+pub mod rename;
+pub mod trash;
 pub mod util;
\ No newline at end of file
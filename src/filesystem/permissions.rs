@@ -0,0 +1,191 @@
+use std::fs::{self, Metadata, Permissions};
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::{lchown, MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::filesystem::journal::{self, Change};
+use crate::jobs::JobProgress;
+
+/// The mode bits and owner of an entry, as recorded in the journal.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Attributes {
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Attributes {
+    pub fn of(md: &Metadata) -> Attributes {
+        Attributes {
+            mode: md.mode() & 0o7777,
+            uid: md.uid(),
+            gid: md.gid(),
+        }
+    }
+}
+
+/// What a chmod/chown applies. `None` leaves that part of every entry as it is.
+#[derive(Clone)]
+pub struct PermissionChange {
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// Formats a mode like `ls -l`, e.g. `drwxr-xr-x`.
+pub fn mode_string(md: &Metadata) -> String {
+    let file_type = md.file_type();
+    let kind = match () {
+        _ if file_type.is_symlink() => 'l',
+        _ if file_type.is_dir() => 'd',
+        _ => '-',
+    };
+
+    format!("{}{}", kind, mode_bits(md.mode()))
+}
+
+/// Formats the nine permission bits, with setuid, setgid and sticky shown in the execute columns.
+pub fn mode_bits(mode: u32) -> String {
+    let mut bits = String::new();
+
+    for (shift, special, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let part = (mode >> shift) & 0o7;
+
+        bits.push(if part & 0o4 != 0 { 'r' } else { '-' });
+        bits.push(if part & 0o2 != 0 { 'w' } else { '-' });
+        bits.push(match (part & 0o1 != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+
+    bits
+}
+
+/// Parses an octal mode like `755` or `2775`.
+pub fn parse_octal(value: &str) -> Option<u32> {
+    match u32::from_str_radix(value, 8) {
+        Ok(mode) if !value.is_empty() && mode <= 0o7777 => Some(mode),
+        _ => None,
+    }
+}
+
+// The names and ids in /etc/passwd and /etc/group, read once since the details pane asks for owners on every frame
+static USERS: OnceLock<Vec<(String, u32)>> = OnceLock::new();
+static GROUPS: OnceLock<Vec<(String, u32)>> = OnceLock::new();
+
+fn users() -> &'static [(String, u32)] {
+    USERS.get_or_init(|| read_database("/etc/passwd"))
+}
+
+fn groups() -> &'static [(String, u32)] {
+    GROUPS.get_or_init(|| read_database("/etc/group"))
+}
+
+// Reads a colon separated database like /etc/passwd, keeping its order so the first line for a name or id wins
+fn read_database(database: &str) -> Vec<(String, u32)> {
+    let contents = fs::read_to_string(database).unwrap_or_default();
+
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?;
+
+            Some((name.to_string(), id.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Returns the user name for an id, or the id itself when it has no name.
+pub fn user_name(uid: u32) -> String {
+    users().iter().find(|(_, id)| *id == uid).map(|(name, _)| name.clone()).unwrap_or_else(|| uid.to_string())
+}
+
+pub fn group_name(gid: u32) -> String {
+    groups().iter().find(|(_, id)| *id == gid).map(|(name, _)| name.clone()).unwrap_or_else(|| gid.to_string())
+}
+
+/// Returns `user:group` for an entry.
+pub fn owner_string(md: &Metadata) -> String {
+    format!("{}:{}", user_name(md.uid()), group_name(md.gid()))
+}
+
+/// Parses `user`, `user:group` or `:group`, by name or number.
+pub fn parse_owner(value: &str) -> Result<(Option<u32>, Option<u32>), Error> {
+    let resolve = |value: &str, database: &[(String, u32)]| -> Result<Option<u32>, Error> {
+        if value.is_empty() {
+            return Ok(None);
+        }
+        if let Ok(id) = value.parse() {
+            return Ok(Some(id));
+        }
+
+        database
+            .iter()
+            .find(|(name, _)| name == value)
+            .map(|(_, id)| Some(*id))
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("no such user or group: {}", value)))
+    };
+
+    let (user, group) = value.split_once(':').unwrap_or((value, ""));
+    Ok((resolve(user, users())?, resolve(group, groups())?))
+}
+
+/// Applies a change to each path, and to everything inside folders when `recursive` is set.
+pub fn apply(paths: &[String], change: &PermissionChange, recursive: bool, progress: &JobProgress) -> Result<(), Error> {
+    let walk = |path: &String| WalkDir::new(path).max_depth(if recursive { usize::MAX } else { 0 });
+
+    for path in paths {
+        progress.add_total(0, walk(path).into_iter().count() as u64);
+    }
+
+    for path in paths {
+        for entry in walk(path) {
+            progress.checkpoint()?;
+            let entry = entry?;
+
+            set_attributes(entry.path(), change)?;
+            progress.add_done(0, 1);
+        }
+    }
+
+    Ok(())
+}
+
+fn set_attributes(path: &Path, change: &PermissionChange) -> Result<(), Error> {
+    let md = fs::symlink_metadata(path)?;
+    let from = Attributes::of(&md);
+    let to = Attributes {
+        mode: change.mode.unwrap_or(from.mode),
+        uid: change.uid.unwrap_or(from.uid),
+        gid: change.gid.unwrap_or(from.gid),
+    };
+
+    write_attributes(path, &md, from, to)?;
+
+    if from != to {
+        journal::record(Change::Permissions { path: path.to_path_buf(), from, to });
+    }
+
+    Ok(())
+}
+
+/// Sets the mode and owner of an entry. A symlink's own mode can't be changed, so only its owner is.
+pub(crate) fn write_attributes(path: &Path, md: &Metadata, from: Attributes, to: Attributes) -> Result<(), Error> {
+    if from.uid != to.uid || from.gid != to.gid {
+        lchown(path, Some(to.uid), Some(to.gid))?;
+    }
+    if from.mode != to.mode && !md.file_type().is_symlink() {
+        fs::set_permissions(path, Permissions::from_mode(to.mode))?;
+    }
+
+    Ok(())
+}
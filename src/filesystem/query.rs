@@ -0,0 +1,468 @@
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::time::SystemTime;
+
+use regex::Regex;
+
+use crate::filesystem::index::IndexEntry;
+use crate::fuzzy;
+
+/// A parsed search. Bare words fuzzy match the path, `key:value` words filter on what the index knows.
+/// Words are combined with NOT, AND and OR, from tightest to loosest, and AND is implied between words.
+pub enum Query {
+    All,
+    Fuzzy(String),
+    Filter(Filter),
+    Not(Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+}
+
+pub enum Filter {
+    /// `ext:rs` or `ext:rs,toml`
+    Extension(Vec<String>),
+    /// `size:>10M`
+    Size(Comparison),
+    /// `modified:<7d`, compared against how long ago the entry was modified
+    Modified(Comparison),
+    /// `type:dir` or `type:file`
+    Directory(bool),
+    /// `path:src/`, matched against the path relative to the index root
+    Path(Regex),
+    /// `name:main` or `name:/^ma.n\.rs$/`
+    Name(Regex),
+}
+
+#[derive(Clone, Copy)]
+pub enum Operator {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+}
+
+/// A number with a unit, e.g. `>10M`. Without an operator it matches anything that rounds down to the number in that unit.
+pub struct Comparison {
+    pub operator: Operator,
+    pub amount: u64,
+    pub unit: u64,
+}
+
+impl Comparison {
+    fn matches(&self, value: u64) -> bool {
+        let threshold = self.amount.saturating_mul(self.unit);
+
+        match self.operator {
+            Operator::Less => value < threshold,
+            Operator::LessOrEqual => value <= threshold,
+            Operator::Greater => value > threshold,
+            Operator::GreaterOrEqual => value >= threshold,
+            Operator::Equal => value / self.unit == self.amount,
+        }
+    }
+}
+
+enum Token {
+    Open,
+    Close,
+    Word(String),
+}
+
+pub fn parse(text: &str) -> Result<Query, Error> {
+    let tokens = tokenize(text)?;
+    if tokens.is_empty() {
+        return Ok(Query::All);
+    }
+
+    let mut parser = Parser { tokens, position: 0 };
+    let query = parser.or()?;
+
+    match parser.tokens.get(parser.position) {
+        None => Ok(query),
+        Some(Token::Close) => Err(invalid("unmatched )".to_string())),
+        Some(_) => Err(invalid("expected AND or OR".to_string())),
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+// Splits on spaces and parentheses. Double quotes keep spaces and parentheses inside a word,
+// and so does a `/regex/` right after a `key:`, up to the first `/` that isn't escaped
+fn tokenize(text: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut rest = text;
+
+    let finish = |word: &mut String, tokens: &mut Vec<Token>| {
+        if !word.is_empty() {
+            tokens.push(Token::Word(std::mem::take(word)));
+        }
+    };
+
+    while let Some(c) = rest.chars().next() {
+        let mut length = c.len_utf8();
+
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => word.push(c),
+            '/' if word.ends_with(':') => {
+                // Without a closing `/` it's just the start of a path
+                length = regex_length(rest).unwrap_or(length);
+                word.push_str(&rest[..length]);
+            },
+            '(' | ')' => {
+                finish(&mut word, &mut tokens);
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            },
+            _ if c.is_whitespace() => finish(&mut word, &mut tokens),
+            _ => word.push(c),
+        }
+
+        rest = &rest[length..];
+    }
+
+    if quoted {
+        return Err(invalid("unclosed quote".to_string()));
+    }
+    finish(&mut word, &mut tokens);
+
+    Ok(tokens)
+}
+
+// The length of the `/regex/` at the start of `text`, including both slashes
+fn regex_length(text: &str) -> Option<usize> {
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '/' => return Some(i + 1),
+            _ => {},
+        }
+    }
+
+    None
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word)) if word == keyword => {
+                self.position += 1;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Query, Error> {
+        let mut query = self.and()?;
+
+        while self.keyword("OR") {
+            if self.position == self.tokens.len() {
+                return Err(invalid("OR needs something after it".to_string()));
+            }
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query, Error> {
+        let mut query = self.not()?;
+
+        loop {
+            let explicit = self.keyword("AND");
+            let more = match self.tokens.get(self.position) {
+                Some(Token::Word(word)) => word != "OR",
+                Some(Token::Open) => true,
+                Some(Token::Close) | None => false,
+            };
+
+            match more {
+                true => query = Query::And(Box::new(query), Box::new(self.not()?)),
+                false if explicit => return Err(invalid("AND needs something after it".to_string())),
+                false => return Ok(query),
+            }
+        }
+    }
+
+    fn not(&mut self) -> Result<Query, Error> {
+        match self.keyword("NOT") {
+            true => Ok(Query::Not(Box::new(self.not()?))),
+            false => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Query, Error> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+
+        match token {
+            Some(Token::Open) => {
+                let query = self.or()?;
+                match self.tokens.get(self.position) {
+                    Some(Token::Close) => {
+                        self.position += 1;
+                        Ok(query)
+                    },
+                    _ => Err(invalid("unclosed (".to_string())),
+                }
+            },
+            Some(Token::Word(word)) if word == "AND" || word == "OR" => Err(invalid(format!("{} needs something before it", word))),
+            Some(Token::Word(word)) => parse_word(word),
+            Some(Token::Close) => Err(invalid("unmatched )".to_string())),
+            None => Err(invalid("NOT needs something after it".to_string())),
+        }
+    }
+}
+
+fn parse_word(word: &str) -> Result<Query, Error> {
+    let (key, value) = match word.split_once(':') {
+        Some(data) => data,
+        None => return Ok(Query::Fuzzy(word.to_string())),
+    };
+
+    let filter = match key {
+        "ext" => Filter::Extension(value.split(',').map(|extension| extension.trim_start_matches('.').to_lowercase()).collect()),
+        "size" => Filter::Size(parse_comparison(value, size_unit)?),
+        "modified" => Filter::Modified(parse_comparison(value, age_unit)?),
+        "type" => Filter::Directory(match value {
+            "dir" | "d" | "folder" | "directory" => true,
+            "file" | "f" => false,
+            _ => return Err(invalid(format!("type should be dir or file, not {}", value))),
+        }),
+        "path" => Filter::Path(parse_pattern(value)?),
+        "name" => Filter::Name(parse_pattern(value)?),
+        // Anything else is part of a path, like `C:`
+        _ => return Ok(Query::Fuzzy(word.to_string())),
+    };
+
+    Ok(Query::Filter(filter))
+}
+
+// `/regex/` is a regex, anything else is text to look for ignoring case
+fn parse_pattern(value: &str) -> Result<Regex, Error> {
+    let pattern = match value.strip_prefix('/').and_then(|value| value.strip_suffix('/')) {
+        Some(pattern) => pattern.to_string(),
+        None => format!("(?i){}", regex::escape(value)),
+    };
+
+    // The last line of a regex error says what's wrong, the rest points at where
+    Regex::new(&pattern).map_err(|e| invalid(e.to_string().lines().last().unwrap_or_default().trim().to_string()))
+}
+
+fn parse_comparison(value: &str, unit: fn(&str) -> Option<u64>) -> Result<Comparison, Error> {
+    let (operator, rest) = [
+        (">=", Operator::GreaterOrEqual),
+        ("<=", Operator::LessOrEqual),
+        (">", Operator::Greater),
+        ("<", Operator::Less),
+        ("=", Operator::Equal),
+    ]
+    .into_iter()
+    .find_map(|(prefix, operator)| value.strip_prefix(prefix).map(|rest| (operator, rest)))
+    .unwrap_or((Operator::Equal, value));
+
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let amount = rest[..digits].parse().map_err(|_| invalid(format!("expected a number in {}", value)))?;
+    let unit = unit(&rest[digits..]).ok_or_else(|| invalid(format!("unknown unit in {}", value)))?;
+
+    Ok(Comparison { operator, amount, unit })
+}
+
+// Sizes are in bytes, with binary prefixes: `10`, `10k`, `10M`, `10MB`, `10MiB`
+fn size_unit(unit: &str) -> Option<u64> {
+    let unit = unit.to_lowercase();
+    let prefix = unit.trim_end_matches('b').trim_end_matches('i');
+
+    let power = match prefix {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        _ => return None,
+    };
+
+    Some(1024u64.pow(power))
+}
+
+// Ages are in seconds: `30s`, `15m`, `2h`, `7d`, `4w`, `1y`
+fn age_unit(unit: &str) -> Option<u64> {
+    match unit {
+        "s" => Some(1),
+        "m" => Some(60),
+        "h" => Some(60 * 60),
+        "" | "d" => Some(24 * 60 * 60),
+        "w" => Some(7 * 24 * 60 * 60),
+        "y" => Some(365 * 24 * 60 * 60),
+        _ => None,
+    }
+}
+
+impl Query {
+    /// Returns the fuzzy score and the matched char positions in `relative` when the entry matches.
+    /// `scratch` is reused by fuzzy matching from one entry to the next.
+    pub fn matches(&self, entry: &IndexEntry, relative: &str, now: SystemTime, scratch: &mut fuzzy::Scratch) -> Option<(i64, Vec<usize>)> {
+        match self {
+            Query::All => Some((0, Vec::new())),
+            Query::Fuzzy(term) => fuzzy::score_with(term, relative, scratch),
+            Query::Filter(filter) => filter.matches(entry, relative, now).then(|| (0, Vec::new())),
+            Query::Not(query) => match query.matches(entry, relative, now, scratch) {
+                Some(_) => None,
+                None => Some((0, Vec::new())),
+            },
+            Query::And(a, b) => {
+                let (score, mut positions) = a.matches(entry, relative, now, scratch)?;
+                let (other, more) = b.matches(entry, relative, now, scratch)?;
+
+                positions.extend(more);
+                Some((score + other, positions))
+            },
+            Query::Or(a, b) => match (a.matches(entry, relative, now, scratch), b.matches(entry, relative, now, scratch)) {
+                (Some((score, mut positions)), Some((other, more))) => {
+                    positions.extend(more);
+                    Some((score.max(other), positions))
+                },
+                (a, b) => a.or(b),
+            },
+        }
+    }
+}
+
+impl Filter {
+    fn matches(&self, entry: &IndexEntry, relative: &str, now: SystemTime) -> bool {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+
+        match self {
+            Filter::Extension(extensions) => !entry.is_dir && Path::new(name)
+                .extension()
+                .is_some_and(|extension| extensions.contains(&extension.to_string_lossy().to_lowercase())),
+            Filter::Size(comparison) => !entry.is_dir && comparison.matches(entry.size),
+            // Something modified in the future counts as just modified
+            Filter::Modified(comparison) => comparison.matches(now.duration_since(entry.modified).map(|age| age.as_secs()).unwrap_or(0)),
+            Filter::Directory(is_dir) => entry.is_dir == *is_dir,
+            Filter::Path(pattern) => pattern.is_match(relative),
+            Filter::Name(pattern) => pattern.is_match(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        tokenize(text)
+            .unwrap()
+            .into_iter()
+            .map(|token| match token {
+                Token::Open => "(".to_string(),
+                Token::Close => ")".to_string(),
+                Token::Word(word) => word,
+            })
+            .collect()
+    }
+
+    // Writes a query out with explicit grouping, e.g. `(or (and a b) c)`
+    fn shape(query: &Query) -> String {
+        match query {
+            Query::All => "all".to_string(),
+            Query::Fuzzy(term) => term.clone(),
+            Query::Filter(_) => "filter".to_string(),
+            Query::Not(query) => format!("(not {})", shape(query)),
+            Query::And(a, b) => format!("(and {} {})", shape(a), shape(b)),
+            Query::Or(a, b) => format!("(or {} {})", shape(a), shape(b)),
+        }
+    }
+
+    fn parsed(text: &str) -> String {
+        shape(&parse(text).unwrap())
+    }
+
+    fn error(text: &str) -> String {
+        match parse(text) {
+            Ok(query) => panic!("{} parsed as {}", text, shape(&query)),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parsed(""), "all");
+        assert_eq!(parsed("a b OR c"), "(or (and a b) c)");
+        assert_eq!(parsed("a AND b OR c AND d"), "(or (and a b) (and c d))");
+        assert_eq!(parsed("NOT a b"), "(and (not a) b)");
+        assert_eq!(parsed("NOT NOT a"), "(not (not a))");
+        assert_eq!(parsed("a (b OR c)"), "(and a (or b c))");
+        assert_eq!(parsed("(a OR b) NOT (c d)"), "(and (or a b) (not (and c d)))");
+    }
+
+    #[test]
+    fn words_and_filters() {
+        assert_eq!(parsed(r#""a b" ext:rs"#), "(and a b filter)");
+        assert_eq!(parsed("C:stuff size:>10M"), "(and C:stuff filter)");
+        // Keywords only count in capitals
+        assert_eq!(parsed("a or b"), "(and (and a or) b)");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("NOT"), "NOT needs something after it");
+        assert_eq!(error("OR a"), "OR needs something before it");
+        assert_eq!(error("a AND"), "AND needs something after it");
+        assert_eq!(error("(a b"), "unclosed (");
+        assert_eq!(error("a b)"), "unmatched )");
+        assert_eq!(error(r#"a "b"#), "unclosed quote");
+        assert_eq!(error("type:pipe"), "type should be dir or file, not pipe");
+        assert_eq!(error("size:>10Q"), "unknown unit in >10Q");
+    }
+
+    #[test]
+    fn trailing_or_is_missing_its_right_side() {
+        assert_eq!(error("a OR"), "OR needs something after it");
+        assert_eq!(error("a OR b OR"), "OR needs something after it");
+        assert_eq!(parsed("a OR NOT b"), "(or a (not b))");
+    }
+
+    #[test]
+    fn comparisons() {
+        let size = parse_comparison(">=2k", size_unit).unwrap();
+        assert!(size.matches(2048) && size.matches(4096) && !size.matches(2047));
+
+        // Without an operator it's anything that rounds down to the amount
+        let age = parse_comparison("3d", age_unit).unwrap();
+        assert!(age.matches(3 * 24 * 60 * 60 + 5) && !age.matches(2 * 24 * 60 * 60));
+    }
+
+    #[test]
+    fn regex_literal_is_one_word() {
+        assert_eq!(words(r"name:/(foo|bar)\.rs/ (ext:rs)"), [r"name:/(foo|bar)\.rs/", "(", "ext:rs", ")"]);
+        assert_eq!(words(r"path:/a\/b (c)/"), [r"path:/a\/b (c)/"]);
+        // No closing slash, so it's a path and the parentheses still split
+        assert_eq!(words("path:/usr (x)"), ["path:/usr", "(", "x", ")"]);
+    }
+
+    #[test]
+    fn regex_literal_parses() {
+        match parse(r"name:/(foo|bar)\.rs/").unwrap() {
+            Query::Filter(Filter::Name(pattern)) => assert!(pattern.is_match("bar.rs") && !pattern.is_match("baz.rs")),
+            _ => panic!("expected a name filter"),
+        }
+        match parse(r"path:/a\/b (c)/").unwrap() {
+            Query::Filter(Filter::Path(pattern)) => assert!(pattern.is_match("a/b c")),
+            _ => panic!("expected a path filter"),
+        }
+    }
+}
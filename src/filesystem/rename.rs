@@ -0,0 +1,469 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Error, ErrorKind, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use regex::{Captures, Regex};
+
+use crate::filesystem::{explorer, util};
+use crate::jobs::JobProgress;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CaseTransform {
+    None,
+    Lower,
+    Upper,
+    Title,
+}
+
+impl CaseTransform {
+    pub fn next(self) -> CaseTransform {
+        match self {
+            CaseTransform::None => CaseTransform::Lower,
+            CaseTransform::Lower => CaseTransform::Upper,
+            CaseTransform::Upper => CaseTransform::Title,
+            CaseTransform::Title => CaseTransform::None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CaseTransform::None => "unchanged",
+            CaseTransform::Lower => "lower",
+            CaseTransform::Upper => "UPPER",
+            CaseTransform::Title => "Title",
+        }
+    }
+
+    fn apply(self, text: &str) -> String {
+        match self {
+            CaseTransform::None => text.to_string(),
+            CaseTransform::Lower => text.to_lowercase(),
+            CaseTransform::Upper => text.to_uppercase(),
+            CaseTransform::Title => {
+                let mut title = String::new();
+                let mut word_start = true;
+
+                for c in text.chars() {
+                    if word_start {
+                        title.extend(c.to_uppercase());
+                    } else {
+                        title.extend(c.to_lowercase());
+                    }
+                    word_start = !c.is_alphanumeric();
+                }
+                title
+            },
+        }
+    }
+}
+
+/// A find and replace applied to every name. `{n}` or `{n:03}` in the replacement is a counter
+/// over the matching entries, and `$1` or `${name}` are capture groups when `regex` is set.
+pub struct RenamePattern {
+    pub find: String,
+    pub replace: String,
+    pub regex: bool,
+    pub case: CaseTransform,
+}
+
+impl Default for RenamePattern {
+    fn default() -> RenamePattern {
+        RenamePattern {
+            find: String::new(),
+            replace: String::new(),
+            regex: true,
+            case: CaseTransform::None,
+        }
+    }
+}
+
+/// Works out the new name of every source. An empty find matches nothing, so every name stays as it is.
+pub fn pattern_names(sources: &[String], pattern: &RenamePattern) -> Result<Vec<String>, regex::Error> {
+    if pattern.find.is_empty() {
+        return Ok(sources.iter().map(|source| util::strip_directory(source)).collect());
+    }
+
+    let find = match pattern.regex {
+        true => Regex::new(&pattern.find)?,
+        false => Regex::new(&regex::escape(&pattern.find))?,
+    };
+    let counter = Regex::new(r"\{n(?::(0?)(\d+))?\}").expect("counter pattern is valid");
+
+    let mut count = 0;
+    let names = sources
+        .iter()
+        .map(|source| {
+            let name = util::strip_directory(source);
+            if !find.is_match(&name) {
+                return name;
+            }
+            count += 1;
+
+            find.replace_all(&name, |caps: &Captures| {
+                // Groups are expanded in the text between counters, so `$1{n}` is group 1 followed by the count rather than group 11
+                let mut replaced = String::new();
+                let mut expand = |text: &str| match pattern.regex {
+                    true => caps.expand(text, &mut replaced),
+                    // Outside regex mode a `$` is just a character
+                    false => replaced.push_str(text),
+                };
+
+                let mut last = 0;
+                for placeholder in counter.captures_iter(&pattern.replace) {
+                    let whole = placeholder.get(0).expect("group 0 is the whole match");
+                    expand(&pattern.replace[last..whole.start()]);
+                    last = whole.end();
+
+                    let width = placeholder.get(2).and_then(|width| width.as_str().parse().ok()).unwrap_or(0);
+                    expand(&match placeholder.get(1).is_some_and(|zero| !zero.as_str().is_empty()) {
+                        true => format!("{:0width$}", count, width = width),
+                        false => format!("{:width$}", count, width = width),
+                    });
+                }
+                expand(&pattern.replace[last..]);
+
+                pattern.case.apply(&replaced)
+            })
+            .into_owned()
+        })
+        .collect();
+
+    Ok(names)
+}
+
+/// Builds a plan from a pattern, reporting an invalid regex as a problem in the preview.
+pub fn plan_pattern(sources: &[String], pattern: &RenamePattern) -> RenamePlan {
+    match pattern_names(sources, pattern) {
+        Ok(names) => plan(sources, &names),
+        Err(e) => RenamePlan {
+            sources: sources.to_vec(),
+            names: sources.iter().map(|source| util::strip_directory(source)).collect(),
+            changes: Vec::new(),
+            problems: vec![e.to_string().lines().last().unwrap_or_default().trim().to_string()],
+            steps: Vec::new(),
+            cycles: 0,
+        },
+    }
+}
+
+/// A checked set of renames, shown as a preview before anything is touched.
+pub struct RenamePlan {
+    pub sources: Vec<String>,
+    pub names: Vec<String>,
+    pub changes: Vec<(String, PathBuf)>,
+    pub problems: Vec<String>,
+    // Renames in the order they have to run, with cycles broken through temporary names
+    pub steps: Vec<(PathBuf, PathBuf)>,
+    pub cycles: usize,
+}
+
+impl RenamePlan {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty() && !self.changes.is_empty()
+    }
+}
+
+/// Whether `name` names an entry in its folder rather than a path somewhere else.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.contains('/') && name != "." && name != ".."
+}
+
+/// Pairs each source with its new name, then checks for collisions and works out a safe order.
+pub fn plan(sources: &[String], names: &[String]) -> RenamePlan {
+    let mut plan = RenamePlan {
+        sources: sources.to_vec(),
+        names: names.to_vec(),
+        changes: Vec::new(),
+        problems: Vec::new(),
+        steps: Vec::new(),
+        cycles: 0,
+    };
+
+    if sources.len() != names.len() {
+        plan.problems.push(format!("Expected {} names but found {}. Lines can be changed, not added or removed", sources.len(), names.len()));
+        return plan;
+    }
+
+    let mut targets = HashSet::new();
+    let mut renamed_away = HashSet::new();
+
+    for (source, name) in sources.iter().zip(names) {
+        let name = name.trim_end_matches('\r');
+
+        if name.is_empty() {
+            plan.problems.push(format!("{} was given an empty name", util::strip_directory(source)));
+            continue;
+        }
+        if !is_valid_name(name) {
+            plan.problems.push(format!("{} is not a valid file name", name));
+            continue;
+        }
+
+        let source_path = std::path::absolute(source).unwrap_or_else(|_| PathBuf::from(source));
+        let target = source_path.with_file_name(name);
+
+        if !targets.insert(target.clone()) {
+            plan.problems.push(format!("{} is used more than once", name));
+        }
+        if target != source_path {
+            renamed_away.insert(source_path);
+            plan.changes.push((source.clone(), target));
+        }
+    }
+
+    // A target may only exist already if its current owner is being renamed out of the way
+    for (_, target) in &plan.changes {
+        if fs::symlink_metadata(target).is_ok() && !renamed_away.contains(target) {
+            plan.problems.push(format!("{} already exists", target.display()));
+        }
+    }
+
+    if plan.problems.is_empty() {
+        order_steps(&mut plan);
+    }
+
+    plan
+}
+
+fn order_steps(plan: &mut RenamePlan) {
+    let mut pending: Vec<(PathBuf, PathBuf)> = plan
+        .changes
+        .iter()
+        .map(|(source, target)| (std::path::absolute(source).unwrap_or_else(|_| PathBuf::from(source)), target.clone()))
+        .collect();
+
+    while !pending.is_empty() {
+        // Run any rename whose target isn't still occupied by another pending source
+        match pending.iter().position(|(_, target)| !pending.iter().any(|(source, _)| source == target)) {
+            Some(index) => plan.steps.push(pending.remove(index)),
+            None => {
+                // Everything left is part of a cycle, so park one entry under a temporary name
+                let (source, target) = pending.remove(0);
+                let temporary = temporary_path(&source);
+
+                plan.steps.push((source, temporary.clone()));
+                pending.push((temporary, target));
+                plan.cycles += 1;
+            },
+        }
+    }
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = util::strip_directory(&path.to_string_lossy());
+    let mut counter = 0;
+
+    loop {
+        let candidate = path.with_file_name(format!(".{}.rfe-rename-{}", name, counter));
+
+        if fs::symlink_metadata(&candidate).is_err() {
+            return candidate;
+        }
+        counter += 1;
+    }
+}
+
+/// Runs the planned renames in order.
+pub fn apply(plan: &RenamePlan, progress: &JobProgress) -> Result<(), Error> {
+    progress.add_total(0, plan.steps.len() as u64);
+
+    for (source, target) in &plan.steps {
+        progress.checkpoint()?;
+        explorer::rename_file(source, target)?;
+        progress.add_done(0, 1);
+    }
+
+    Ok(())
+}
+
+/// Makes a folder only the user can get into and returns the file in it that names are edited in.
+/// A fixed name in the shared temp folder could be a symlink someone else left there.
+pub fn edit_file_path() -> Result<PathBuf, Error> {
+    loop {
+        let directory = std::env::temp_dir().join(format!("rfe-rename-{}-{:08x}", std::process::id(), rand::random::<u32>()));
+
+        match fs::DirBuilder::new().mode(0o700).create(&directory) {
+            Ok(_) => return Ok(directory.join("names.txt")),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Removes the edited file along with its folder and anything the editor left next to it.
+pub fn remove_edit_file(path: &Path) {
+    if let Some(directory) = path.parent() {
+        let _ = fs::remove_dir_all(directory);
+    }
+}
+
+/// Writes one name per line for the editor, to a file that mustn't exist yet.
+pub fn write_names(path: &Path, names: &[String]) -> Result<(), Error> {
+    if let Some(name) = names.iter().find(|name| name.contains('\n')) {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{:?} has a line break in its name", name)));
+    }
+
+    let mut contents = names.join("\n");
+    contents.push('\n');
+
+    fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?.write_all(contents.as_bytes())
+}
+
+pub fn read_names(path: &Path) -> Result<Vec<String>, Error> {
+    Ok(fs::read_to_string(path)?.lines().map(|line| line.to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A folder of empty files, removed again when dropped
+    struct Folder(PathBuf);
+
+    impl Folder {
+        fn new(test: &str, files: &[&str]) -> Folder {
+            let path = std::env::temp_dir().join(format!("rfe-rename-test-{}-{}", std::process::id(), test));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            for file in files {
+                fs::write(path.join(file), "").unwrap();
+            }
+            Folder(path)
+        }
+
+        fn paths(&self, files: &[&str]) -> Vec<String> {
+            files.iter().map(|file| self.0.join(file).to_string_lossy().into_owned()).collect()
+        }
+    }
+
+    impl Drop for Folder {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    // Runs the steps over a map of file names, the way they'd run on disk
+    fn simulate(plan: &RenamePlan, files: &[&str]) -> Vec<String> {
+        let mut present: HashSet<String> = files.iter().map(|file| file.to_string()).collect();
+
+        for (source, target) in &plan.steps {
+            let source = util::strip_directory(&source.to_string_lossy());
+            let target = util::strip_directory(&target.to_string_lossy());
+            assert!(present.remove(&source), "{} is renamed but isn't there", source);
+            assert!(present.insert(target.clone()), "{} is overwritten", target);
+        }
+
+        let mut present: Vec<String> = present.into_iter().collect();
+        present.sort();
+        present
+    }
+
+    #[test]
+    fn swap_goes_through_a_temporary_name() {
+        let folder = Folder::new("swap", &["a", "b"]);
+        let plan = plan(&folder.paths(&["a", "b"]), &names(&["b", "a"]));
+
+        assert!(plan.problems.is_empty());
+        assert_eq!(plan.changes.len(), 2);
+        assert_eq!(plan.cycles, 1);
+        assert_eq!(plan.steps.len(), 3);
+        assert_eq!(simulate(&plan, &["a", "b"]), ["a", "b"]);
+    }
+
+    #[test]
+    fn cycle_of_three() {
+        let folder = Folder::new("cycle", &["a", "b", "c"]);
+        let plan = plan(&folder.paths(&["a", "b", "c"]), &names(&["b", "c", "a"]));
+
+        assert!(plan.problems.is_empty());
+        assert_eq!(plan.cycles, 1);
+        assert_eq!(plan.steps.len(), 4);
+        assert_eq!(simulate(&plan, &["a", "b", "c"]), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn chain_runs_from_the_free_end() {
+        let folder = Folder::new("chain", &["a", "b"]);
+        let plan = plan(&folder.paths(&["a", "b"]), &names(&["b", "c"]));
+
+        assert!(plan.problems.is_empty());
+        assert_eq!(plan.cycles, 0);
+        assert_eq!(simulate(&plan, &["a", "b"]), ["b", "c"]);
+    }
+
+    #[test]
+    fn duplicate_targets_are_a_problem() {
+        let folder = Folder::new("duplicate", &["a", "b"]);
+        let plan = plan(&folder.paths(&["a", "b"]), &names(&["x", "x"]));
+
+        assert_eq!(plan.problems, ["x is used more than once"]);
+        assert!(plan.steps.is_empty());
+        assert!(!plan.is_valid());
+    }
+
+    #[test]
+    fn existing_target_is_a_problem_unless_renamed_away() {
+        let folder = Folder::new("existing", &["a", "b"]);
+
+        let blocked = plan(&folder.paths(&["a"]), &names(&["b"]));
+        assert_eq!(blocked.problems.len(), 1);
+        assert!(blocked.problems[0].ends_with("b already exists"));
+
+        let freed = plan(&folder.paths(&["a", "b"]), &names(&["b", "c"]));
+        assert!(freed.problems.is_empty());
+    }
+
+    #[test]
+    fn bad_names_are_problems() {
+        let folder = Folder::new("bad", &["a", "b", "c"]);
+        let plan = plan(&folder.paths(&["a", "b", "c"]), &names(&["", "x/y", ".."]));
+
+        assert_eq!(plan.problems, ["a was given an empty name", "x/y is not a valid file name", ".. is not a valid file name"]);
+    }
+
+    #[test]
+    fn wrong_number_of_names() {
+        let plan = plan(&names(&["/tmp/a", "/tmp/b"]), &names(&["a"]));
+
+        assert_eq!(plan.problems.len(), 1);
+        assert!(plan.problems[0].starts_with("Expected 2 names but found 1"));
+    }
+
+    #[test]
+    fn pattern_names_with_counter_and_groups() {
+        let sources = names(&["/tmp/IMG_1.jpg", "/tmp/notes.txt", "/tmp/IMG_2.jpg"]);
+        let pattern = RenamePattern {
+            find: r"IMG_(\d+)".to_string(),
+            replace: "photo-{n:02}-$1".to_string(),
+            ..RenamePattern::default()
+        };
+
+        assert_eq!(pattern_names(&sources, &pattern).unwrap(), ["photo-01-1.jpg", "notes.txt", "photo-02-2.jpg"]);
+    }
+
+    #[test]
+    fn counter_right_after_a_group() {
+        let sources = names(&["/tmp/a.txt", "/tmp/b.txt"]);
+        let pattern = RenamePattern {
+            find: r"^(\w)".to_string(),
+            replace: "$1{n}".to_string(),
+            ..RenamePattern::default()
+        };
+
+        assert_eq!(pattern_names(&sources, &pattern).unwrap(), ["a1.txt", "b2.txt"]);
+    }
+
+    #[test]
+    fn empty_find_changes_nothing() {
+        let sources = names(&["/tmp/a.txt", "/tmp/b.txt"]);
+        let pattern = RenamePattern { replace: "x".to_string(), ..RenamePattern::default() };
+
+        assert_eq!(pattern_names(&sources, &pattern).unwrap(), ["a.txt", "b.txt"]);
+    }
+}
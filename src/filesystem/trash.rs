@@ -0,0 +1,282 @@
+use std::fs::{self, OpenOptions};
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveDateTime};
+
+use crate::filesystem::{explorer, util};
+use crate::jobs::JobProgress;
+
+/// A single entry in the trash, built from its `.trashinfo` record.
+pub struct TrashEntry {
+    pub name: String,
+    pub original_path: String,
+    pub deletion_date: String,
+    pub trashed_path: PathBuf,
+}
+
+/// Returns the home trash directory, `$XDG_DATA_HOME/Trash`.
+pub fn trash_dir() -> PathBuf {
+    util::xdg_data_home().join("Trash")
+}
+
+fn files_dir() -> PathBuf {
+    trash_dir().join("files")
+}
+
+fn info_dir() -> PathBuf {
+    trash_dir().join("info")
+}
+
+fn info_path(name: &str) -> PathBuf {
+    info_dir().join(format!("{}.trashinfo", name))
+}
+
+/// Moves a file or folder into the trash and writes its `.trashinfo` record.
+pub fn trash_path(path: &str, progress: &JobProgress) -> Result<PathBuf, Error> {
+    let source = std::path::absolute(path)?;
+    let file_name = util::file_name(path).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "path has no file name"))?;
+
+    fs::create_dir_all(files_dir())?;
+    fs::create_dir_all(info_dir())?;
+
+    // The info file is created first and exclusively so two trash calls can never pick the same name
+    let mut counter = 1;
+    let (name, mut info_file) = loop {
+        let candidate = if counter == 1 { file_name.clone() } else { format!("{}.{}", file_name, counter) };
+
+        if !files_dir().join(&candidate).exists() {
+            match OpenOptions::new().write(true).create_new(true).open(info_path(&candidate)) {
+                Ok(file) => break (candidate, file),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {},
+                Err(e) => return Err(e),
+            }
+        }
+        counter += 1;
+    };
+
+    let deletion_date = Local::now().format("%Y-%m-%dT%H:%M:%S");
+    info_file.write_all(format!("[Trash Info]\nPath={}\nDeletionDate={}\n", encode_path(&source), deletion_date).as_bytes())?;
+
+    let destination = files_dir().join(&name);
+    if let Err(e) = explorer::relocate(&source, &destination, progress) {
+        let _ = fs::remove_file(info_path(&name));
+        return Err(e);
+    }
+
+    Ok(destination)
+}
+
+/// Lists everything currently in the trash, newest first, along with how many records couldn't be read.
+/// A broken `.trashinfo` is skipped rather than hiding the rest of the trash.
+pub fn list_trash() -> Result<(Vec<TrashEntry>, usize), Error> {
+    let mut entries = Vec::new();
+    let mut unreadable = 0;
+
+    let infos = match fs::read_dir(info_dir()) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((entries, 0)),
+        Err(e) => return Err(e),
+    };
+
+    for info in infos {
+        let info = match info {
+            Ok(data) => data.path(),
+            Err(_) => {
+                unreadable += 1;
+                continue;
+            },
+        };
+        let name = match info.file_name().and_then(|name| name.to_str()).and_then(|name| name.strip_suffix(".trashinfo")) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        match read_info(&info) {
+            Some((original_path, deletion_date)) => entries.push(TrashEntry {
+                trashed_path: files_dir().join(&name),
+                name,
+                original_path,
+                deletion_date,
+            }),
+            None => unreadable += 1,
+        }
+    }
+
+    entries.sort_by_key(|entry| std::cmp::Reverse(NaiveDateTime::parse_from_str(&entry.deletion_date, "%Y-%m-%dT%H:%M:%S").ok()));
+
+    Ok((entries, unreadable))
+}
+
+// Returns the original path and deletion date, or `None` when the record can't be read or has no path
+fn read_info(info: &Path) -> Option<(String, String)> {
+    let contents = fs::read_to_string(info).ok()?;
+    let mut original_path = None;
+    let mut deletion_date = String::new();
+
+    for line in contents.lines() {
+        if let Some(value) = line.strip_prefix("Path=") {
+            original_path = Some(decode_path(value));
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            deletion_date = value.to_string();
+        }
+    }
+
+    Some((original_path?, deletion_date))
+}
+
+/// Moves a trashed entry back to where it was deleted from.
+pub fn restore(entry: &TrashEntry, progress: &JobProgress) -> Result<(), Error> {
+    let original = Path::new(&entry.original_path);
+
+    if fs::symlink_metadata(original).is_ok() {
+        return Err(Error::new(ErrorKind::AlreadyExists, format!("{} already exists", entry.original_path)));
+    }
+
+    if let Some(parent) = original.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    explorer::relocate(&entry.trashed_path, original, progress)?;
+    fs::remove_file(info_path(&entry.name))
+}
+
+/// Removes a single entry from the trash for good.
+pub fn purge(entry: &TrashEntry, progress: &JobProgress) -> Result<(), Error> {
+    explorer::remove_recursive(&entry.trashed_path, progress)?;
+    fs::remove_file(info_path(&entry.name))
+}
+
+/// Permanently removes everything in the trash.
+pub fn empty_trash(progress: &JobProgress) -> Result<(), Error> {
+    let (entries, _) = list_trash()?;
+
+    for entry in &entries {
+        let (bytes, files) = util::measure(&entry.trashed_path);
+        progress.add_total(bytes, files);
+    }
+
+    for entry in &entries {
+        purge(entry, progress)?;
+    }
+
+    Ok(())
+}
+
+fn encode_path(path: &Path) -> String {
+    let mut res = String::new();
+
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => res.push(byte as char),
+            _ => res.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    res
+}
+
+fn decode_path(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(byte) = std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                res.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        res.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&res).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::util::TestHome;
+
+    fn trashed(entries: &[TrashEntry]) -> Vec<(String, String)> {
+        let mut found: Vec<(String, String)> = entries.iter().map(|entry| (entry.name.clone(), entry.original_path.clone())).collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn paths_are_percent_encoded() {
+        let path = Path::new("/home/me/a b/100%_ünï.txt");
+        let encoded = encode_path(path);
+
+        assert_eq!(encoded, "/home/me/a%20b/100%25_%C3%BCn%C3%AF.txt");
+        assert_eq!(decode_path(&encoded), path.to_string_lossy());
+    }
+
+    #[test]
+    fn broken_escapes_are_kept_as_they_are() {
+        assert_eq!(decode_path("/tmp/%zz%4"), "/tmp/%zz%4");
+        assert_eq!(decode_path("/tmp/%2"), "/tmp/%2");
+        assert_eq!(decode_path("%41%42"), "AB");
+    }
+
+    #[test]
+    fn same_names_get_numbered_and_restore_to_their_own_folders() {
+        let home = TestHome::new("trash-collisions");
+        let folders = [home.path.join("one"), home.path.join("two")];
+        for (i, folder) in folders.iter().enumerate() {
+            fs::create_dir_all(folder).unwrap();
+            fs::write(folder.join("notes.txt"), i.to_string()).unwrap();
+            trash_path(&folder.join("notes.txt").to_string_lossy(), &JobProgress::default()).unwrap();
+        }
+
+        let (entries, unreadable) = list_trash().unwrap();
+        let original = |i: usize| folders[i].join("notes.txt").to_string_lossy().into_owned();
+        assert_eq!(unreadable, 0);
+        assert_eq!(trashed(&entries), [("notes.txt".to_string(), original(0)), ("notes.txt.2".to_string(), original(1))]);
+
+        for entry in &entries {
+            restore(entry, &JobProgress::default()).unwrap();
+        }
+        assert_eq!(fs::read_to_string(original(0)).unwrap(), "0");
+        assert_eq!(fs::read_to_string(original(1)).unwrap(), "1");
+        assert_eq!(list_trash().unwrap().0.len(), 0);
+        assert_eq!(fs::read_dir(files_dir()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn restore_never_replaces_what_is_there_now() {
+        let home = TestHome::new("trash-restore-existing");
+        let path = home.path.join("notes.txt");
+        fs::write(&path, "old").unwrap();
+        trash_path(&path.to_string_lossy(), &JobProgress::default()).unwrap();
+        fs::write(&path, "new").unwrap();
+
+        let (entries, _) = list_trash().unwrap();
+        let e = restore(&entries[0], &JobProgress::default()).unwrap_err();
+
+        assert_eq!(e.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(list_trash().unwrap().0.len(), 1);
+    }
+
+    #[test]
+    fn unreadable_records_are_skipped_and_counted() {
+        let home = TestHome::new("trash-unreadable");
+        let path = home.path.join("kept.txt");
+        fs::write(&path, "").unwrap();
+        trash_path(&path.to_string_lossy(), &JobProgress::default()).unwrap();
+
+        // No Path line, not UTF-8, and a file that isn't a record at all
+        fs::write(info_path("no-path"), "[Trash Info]\nDeletionDate=2024-01-01T00:00:00\n").unwrap();
+        fs::write(info_path("binary"), [0xff, 0xfe, 0x00]).unwrap();
+        fs::write(info_dir().join("README"), "not a record").unwrap();
+
+        let (entries, unreadable) = list_trash().unwrap();
+        assert_eq!(trashed(&entries), [("kept.txt".to_string(), path.to_string_lossy().into_owned())]);
+        assert_eq!(unreadable, 2);
+    }
+}
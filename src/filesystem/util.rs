@@ -1,20 +1,19 @@
-use std::path::Path;
-use std::fs::{self, metadata, Metadata};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::time::SystemTime;
 
 use walkdir::WalkDir;
-use std::collections::HashMap;
 
 use chrono::offset::Utc;
 use chrono::DateTime;
-use std::time::SystemTime;
 
 use std::env;
 
 
 
-pub fn update_current_directory(selected_directory: &String, current_directory: &mut String) {
+pub fn update_current_directory(selected_directory: &str, current_directory: &mut String) {
     current_directory.clear();
-    current_directory.push_str(&selected_directory);
+    current_directory.push_str(selected_directory);
 }
 
 pub fn move_up_in_path(path_str: &String) -> Result<Option<String>, std::io::Error>{
@@ -32,7 +31,7 @@ pub fn is_path_file(metadata: &fs::Metadata) -> Result<String, std::io::Error> {
     if is_path_file{
         return Ok("File".to_string());
     }
-    return Ok("Folder".to_string());
+    Ok("Folder".to_string())
 }
 
 pub fn get_size_in_mb(metadata: &fs::Metadata) -> Result<f64, std::io::Error> {
@@ -45,39 +44,36 @@ pub fn last_modified_time(metadata: &fs::Metadata) -> Result<String, std::io::Er
 
     let modified_time = metadata.modified()?;
 
-    let datetime: DateTime<Utc> = modified_time.into();
-    let res = format!("{}", datetime.format("%d/%m/%Y %T"));
-
-    Ok(res)
+    Ok(format_time(modified_time))
 }
 
-pub fn file_name(path: &str) -> Option<String> {
-    Path::new(path)
-        .file_name()
-        .and_then(|name| name.to_str())
-        .map(|s| s.to_string())
+pub fn format_time(time: SystemTime) -> String {
+    let datetime: DateTime<Utc> = time.into();
+    format!("{}", datetime.format("%d/%m/%Y %T"))
 }
 
+/// Formats a byte count with the largest unit that keeps it above 1, like `4.2 KB`.
+pub fn human_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
 
-pub fn get_files_in_directory(path: &String) -> Result<Vec<String>, std::io::Error> {
-    let paths: fs::ReadDir = fs::read_dir(path)?;
-
-    let mut curr_dir = Vec::new();
-
-    for path in paths {
-        let curr = path.unwrap().path();
-        let mut my_str = curr
-            .into_os_string()
-            .into_string()
-            .unwrap()
-            .replace("\\", "/");
-
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
 
-        curr_dir.push(my_str);
-        
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, units[unit]),
     }
+}
 
-    Ok(curr_dir)
+pub fn file_name(path: &str) -> Option<String> {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(|s| s.to_string())
 }
 
 
@@ -85,55 +81,102 @@ pub fn strip_directory(path: &str) -> String{
     path
         .replace("\\", "/")
         .split('/')
-        .last()
+        .next_back()
         .unwrap()
         .to_string()
 }
 
-pub fn get_cahce_fodler() -> Result<String, std::io::Error>{
-    let exe_path = env::current_exe()?;
-    let mut exe_dir = exe_path.to_str().unwrap().to_string();
+/// Deletes a file or folder without going through the trash.
+pub fn remove_path(path: &Path) -> Result<(), std::io::Error> {
+    let md = fs::symlink_metadata(path)?;
 
-    exe_dir = move_up_in_path(&exe_dir).unwrap().unwrap();
-    let cache_dir = format!("{}\\cache", exe_dir);
-
-    Ok(cache_dir)
+    if md.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
 }
 
+/// Returns the total size in bytes and the number of files under a path, without following symlinks.
+pub fn measure(path: &Path) -> (u64, u64) {
+    let mut bytes = 0;
+    let mut files = 0;
 
-pub fn fill_hashmap(path: &str) -> Result<HashMap<String, String>,  std::io::Error>{
-    let mut files: HashMap<String,String> = HashMap::new();
+    for entry in WalkDir::new(path).into_iter().flatten() {
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        bytes += entry.metadata().map(|md| md.len()).unwrap_or(0);
+        files += 1;
+    }
+
+    (bytes, files)
+}
 
-    for entry in WalkDir::new(path) {
-        match entry {
-            Ok(entry) =>{
-                if entry.file_type().is_file() {
+/// Returns `$XDG_DATA_HOME`, falling back to `~/.local/share`.
+pub fn xdg_data_home() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
 
-                    let file_path_str = entry.path().to_string_lossy().to_string();
+/// Returns `$XDG_STATE_HOME`, falling back to `~/.local/state`.
+pub fn xdg_state_home() -> PathBuf {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
 
-                    let file_name_str= strip_directory(&file_path_str);
+/// Returns `$XDG_CONFIG_HOME`, falling back to `~/.config`.
+pub fn xdg_config_home() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
 
+/// Returns `$XDG_CACHE_HOME`, falling back to `~/.cache`.
+pub fn xdg_cache_home() -> PathBuf {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
 
-                    files.insert(file_name_str, file_path_str);
-                }
-                
-            },
-            Err(e) => eprintln!("Error: {}", e),
-        }
+/// Expands a leading `~` to the home directory.
+pub fn expand_home(path: &str) -> String {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("{}{}", env::var("HOME").unwrap_or_default(), rest),
+        _ => path.to_string(),
     }
+}
 
-    Ok(files)
-} 
+fn xdg_dir(variable: &str, fallback: &str) -> PathBuf {
+    match env::var_os(variable) {
+        Some(dir) if Path::new(&dir).is_absolute() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(fallback),
+    }
+}
 
-pub fn search_hash_map(file_name: String, files_hash_map: &HashMap<String, String>) -> Result<Vec<String>, std::io::Error>{
-    let mut res = Vec::new();
+/// Points `$XDG_DATA_HOME` and `$XDG_STATE_HOME` at an empty folder while it's held, so tests of the trash and journal
+/// don't touch the real ones. The environment is shared by every test, so only one can be held at a time.
+#[cfg(test)]
+pub(crate) struct TestHome {
+    pub path: PathBuf,
+    _lock: std::sync::MutexGuard<'static, ()>,
+}
 
-    for (key, _) in &*files_hash_map {
+#[cfg(test)]
+impl TestHome {
+    pub fn new(test: &str) -> TestHome {
+        static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        // A test that failed while holding it leaves it poisoned, which doesn't matter to the next one
+        let lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
 
-        if key.contains(&file_name){
-            res.push(key.clone());
-        }
+        let path = env::temp_dir().join(format!("rfe-test-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        env::set_var("XDG_DATA_HOME", path.join("data"));
+        env::set_var("XDG_STATE_HOME", path.join("state"));
+
+        TestHome { path, _lock: lock }
     }
+}
 
-    Ok(res)
-}
\ No newline at end of file
+#[cfg(test)]
+impl Drop for TestHome {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,175 @@
+/// Buffers kept between calls to `score_with`, so scoring a whole index doesn't allocate a table per candidate.
+#[derive(Default)]
+pub struct Scratch {
+    query: Vec<char>,
+    chars: Vec<char>,
+    bonus: Vec<i64>,
+    // Both hold query.len() rows of chars.len() cells
+    best: Vec<Option<i64>>,
+    from: Vec<usize>,
+}
+
+/// Scores how well `query` matches `candidate` as a subsequence, fzf style.
+/// Returns the score and the char positions that matched, or `None` when it doesn't match at all.
+/// Matching ignores case unless the query has an uppercase letter.
+pub fn score(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    score_with(query, candidate, &mut Scratch::default())
+}
+
+/// The same as `score`, working in `scratch` instead of allocating.
+pub fn score_with(query: &str, candidate: &str, scratch: &mut Scratch) -> Option<(i64, Vec<usize>)> {
+    const MATCH: i64 = 16;
+    const CONSECUTIVE: i64 = 8;
+    const BOUNDARY: i64 = 8;
+    const CAMEL: i64 = 6;
+    const FILE_NAME: i64 = 4;
+    const GAP_START: i64 = 3;
+    const GAP: i64 = 1;
+
+    let case_sensitive = query.chars().any(|c| c.is_uppercase());
+    let fold = |c: char| if case_sensitive { c } else { c.to_ascii_lowercase() };
+
+    if query.is_empty() {
+        return Some((0, Vec::new()));
+    }
+
+    // Cheap check before anything is copied or aligned, since most candidates don't match at all
+    let mut remaining = query.chars().map(fold).peekable();
+    for c in candidate.chars() {
+        if remaining.peek() == Some(&fold(c)) {
+            remaining.next();
+        }
+    }
+    if remaining.peek().is_some() {
+        return None;
+    }
+
+    let Scratch { query: wanted, chars, bonus, best, from } = scratch;
+    wanted.clear();
+    wanted.extend(query.chars().map(fold));
+    chars.clear();
+    chars.extend(candidate.chars());
+
+    let name_start = chars.iter().rposition(|c| *c == '/').map(|i| i + 1).unwrap_or(0);
+    bonus.clear();
+    bonus.extend((0..chars.len()).map(|j| {
+        let previous = if j == 0 { '/' } else { chars[j - 1] };
+        let boundary = match previous {
+            '/' | '_' | '-' | '.' | ' ' => BOUNDARY,
+            _ if previous.is_lowercase() && chars[j].is_uppercase() => CAMEL,
+            _ => 0,
+        };
+        boundary + if j >= name_start { FILE_NAME } else { 0 }
+    }));
+
+    // best[i * n + j] is the best score with query[i] matched at chars[j], and from[i * n + j] where query[i - 1] was matched
+    let n = chars.len();
+    best.clear();
+    best.resize(wanted.len() * n, None);
+    from.clear();
+    from.resize(wanted.len() * n, 0);
+
+    for (i, q) in wanted.iter().enumerate() {
+        // Best earlier cell to jump from with a gap, kept as score + GAP * index so the gap cost can be applied later
+        let mut jump: Option<(i64, usize)> = None;
+
+        for j in 0..n {
+            // Cell j - 2 is the nearest one that leaves a gap before j
+            if i > 0 && j > 1 {
+                if let Some(previous) = best[(i - 1) * n + j - 2] {
+                    let candidate = previous + GAP * (j - 2) as i64;
+                    if jump.is_none_or(|(score, _)| candidate > score) {
+                        jump = Some((candidate, j - 2));
+                    }
+                }
+            }
+            if fold(chars[j]) != *q {
+                continue;
+            }
+
+            let here = MATCH + bonus[j];
+            if i == 0 {
+                best[j] = Some(here - (j as i64).min(15));
+                continue;
+            }
+
+            let consecutive = match j {
+                0 => None,
+                _ => best[(i - 1) * n + j - 1].map(|previous| (previous + here + CONSECUTIVE, j - 1)),
+            };
+            // A gap from cell k to j skips j - k - 1 chars
+            let gapped = jump.map(|(score, k)| (score - GAP * (j - 1) as i64 - GAP_START + here, k));
+
+            let choice = match (consecutive, gapped) {
+                (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                (a, b) => a.or(b),
+            };
+            if let Some((score, k)) = choice {
+                best[i * n + j] = Some(score);
+                from[i * n + j] = k;
+            }
+        }
+    }
+
+    let last = wanted.len() - 1;
+    let (mut j, score) = best[last * n..].iter().enumerate().filter_map(|(j, score)| score.map(|score| (j, score))).max_by_key(|(_, score)| *score)?;
+
+    let mut positions = vec![0; wanted.len()];
+    for i in (0..wanted.len()).rev() {
+        positions[i] = j;
+        j = from[i * n + j];
+    }
+
+    Some((score, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subsequence_or_nothing() {
+        assert_eq!(score("abc", "a_b_c").map(|(_, positions)| positions), Some(vec![0, 2, 4]));
+        assert!(score("abd", "abc").is_none());
+        assert!(score("ba", "ab").is_none());
+        assert_eq!(score("", "anything"), Some((0, Vec::new())));
+    }
+
+    #[test]
+    fn case_is_ignored_until_the_query_has_a_capital() {
+        assert!(score("readme", "README.md").is_some());
+        assert!(score("Readme", "readme.md").is_none());
+        assert!(score("README", "README.md").is_some());
+    }
+
+    #[test]
+    fn consecutive_beats_scattered() {
+        let together = score("main", "src/main.rs").unwrap().0;
+        let scattered = score("main", "src/my_archive_index.rs").unwrap().0;
+        assert!(together > scattered);
+    }
+
+    #[test]
+    fn file_name_beats_folder() {
+        let name = score("conf", "src/config.rs").unwrap().0;
+        let folder = score("conf", "conf/other.rs").unwrap().0;
+        assert!(name > folder);
+    }
+
+    #[test]
+    fn boundaries_are_preferred() {
+        // `fb` should land on the starts of the words, not inside them
+        assert_eq!(score("fb", "foo_bar").unwrap().1, vec![0, 4]);
+        assert_eq!(score("fb", "fooBar").unwrap().1, vec![0, 3]);
+    }
+
+    #[test]
+    fn scratch_gives_the_same_result() {
+        let mut scratch = Scratch::default();
+        let candidates = ["src/filesystem/index.rs", "a", "src/main.rs", "", "docs/index.md"];
+
+        for candidate in candidates {
+            assert_eq!(score_with("index", candidate, &mut scratch), score("index", candidate));
+        }
+    }
+}
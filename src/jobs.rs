@@ -0,0 +1,242 @@
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+
+use crate::filesystem::journal;
+
+/// Progress counters shared between a running operation and the jobs panel.
+/// Operations call `checkpoint` between steps so they can be paused or cancelled.
+#[derive(Default)]
+pub struct JobProgress {
+    pub bytes_done: AtomicU64,
+    pub bytes_total: AtomicU64,
+    pub files_done: AtomicU64,
+    pub files_total: AtomicU64,
+    pub cancelled: AtomicBool,
+    pub paused: AtomicBool,
+    /// Entries the job passed over on purpose, mentioned when it finishes
+    pub skipped: AtomicU64,
+}
+
+impl JobProgress {
+    /// Blocks while the job is paused and fails once it has been cancelled
+    pub fn checkpoint(&self) -> Result<(), Error> {
+        while self.paused.load(Ordering::Relaxed) && !self.cancelled.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(Error::new(ErrorKind::Interrupted, "cancelled"));
+        }
+
+        Ok(())
+    }
+
+    pub fn add_done(&self, bytes: u64, files: u64) {
+        self.bytes_done.fetch_add(bytes, Ordering::Relaxed);
+        self.files_done.fetch_add(files, Ordering::Relaxed);
+    }
+
+    pub fn add_total(&self, bytes: u64, files: u64) {
+        self.bytes_total.fetch_add(bytes, Ordering::Relaxed);
+        self.files_total.fetch_add(files, Ordering::Relaxed);
+    }
+
+    pub fn skip(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    /// Returns how far along the job is, between 0 and 1, by bytes or else by files
+    pub fn ratio(&self) -> f64 {
+        let bytes_total = self.bytes_total.load(Ordering::Relaxed);
+        let files_total = self.files_total.load(Ordering::Relaxed);
+
+        if bytes_total > 0 {
+            (self.bytes_done.load(Ordering::Relaxed) as f64 / bytes_total as f64).min(1.0)
+        } else if files_total > 0 {
+            (self.files_done.load(Ordering::Relaxed) as f64 / files_total as f64).min(1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum JobState {
+    Running,
+    Paused,
+    Done,
+    Cancelled,
+    Failed(String),
+}
+
+pub struct Job {
+    pub id: usize,
+    pub description: String,
+    pub progress: Arc<JobProgress>,
+    pub state: JobState,
+    pub started: Instant,
+    // Time spent paused is left out of the ETA
+    pub paused_for: Duration,
+    pub paused_at: Option<Instant>,
+    handle: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl Job {
+    pub fn is_active(&self) -> bool {
+        matches!(self.state, JobState::Running | JobState::Paused)
+    }
+
+    /// Estimates the time left from the rate so far
+    pub fn eta(&self) -> Option<Duration> {
+        let ratio = self.progress.ratio();
+        if ratio <= 0.0 || !self.is_active() {
+            return None;
+        }
+
+        let paused_now = self.paused_at.map(|at| at.elapsed()).unwrap_or_default();
+        let elapsed = self.started.elapsed().saturating_sub(self.paused_for + paused_now);
+
+        Some(elapsed.mul_f64((1.0 - ratio) / ratio))
+    }
+}
+
+/// Runs long file operations on tokio's blocking pool so the render loop stays responsive
+pub struct JobQueue {
+    pub jobs: Vec<Job>,
+    runtime: Runtime,
+    next_id: usize,
+}
+
+impl Default for JobQueue {
+    fn default() -> JobQueue {
+        JobQueue {
+            jobs: Vec::new(),
+            runtime: tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .expect("can start the job runtime"),
+            next_id: 1,
+        }
+    }
+}
+
+impl JobQueue {
+    pub fn spawn<F>(&mut self, description: String, work: F) -> usize
+    where
+        F: FnOnce(&JobProgress) -> Result<(), Error> + Send + 'static,
+    {
+        let progress = Arc::new(JobProgress::default());
+        let shared = Arc::clone(&progress);
+        let name = description.clone();
+
+        // Everything a job changes is journaled together so it can be undone in one step
+        let handle = self.runtime.spawn_blocking(move || journal::batch(&name, || work(&shared)));
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.jobs.push(Job {
+            id,
+            description,
+            progress,
+            state: JobState::Running,
+            started: Instant::now(),
+            paused_for: Duration::ZERO,
+            paused_at: None,
+            handle: Some(handle),
+        });
+
+        id
+    }
+
+    /// Runs short work that isn't worth listing, like the index search redone on every keystroke.
+    /// It can be cancelled through the returned progress
+    pub fn spawn_untracked<F>(&self, work: F) -> Arc<JobProgress>
+    where
+        F: FnOnce(&JobProgress) -> Result<(), Error> + Send + 'static,
+    {
+        let progress = Arc::new(JobProgress::default());
+        let shared = Arc::clone(&progress);
+
+        drop(self.runtime.spawn_blocking(move || work(&shared)));
+
+        progress
+    }
+
+    /// Collects jobs that have finished since the last call and returns a status line for each
+    pub fn poll(&mut self) -> Vec<String> {
+        let mut finished = Vec::new();
+
+        for job in self.jobs.iter_mut() {
+            let done = match &job.handle {
+                Some(handle) => handle.is_finished(),
+                None => false,
+            };
+            if !done {
+                continue;
+            }
+
+            let result = match job.handle.take() {
+                Some(handle) => self.runtime.block_on(handle),
+                None => continue,
+            };
+
+            job.state = match result {
+                Ok(Ok(_)) => JobState::Done,
+                Ok(Err(e)) if e.kind() == ErrorKind::Interrupted => JobState::Cancelled,
+                Ok(Err(e)) => JobState::Failed(e.to_string()),
+                Err(e) => JobState::Failed(e.to_string()),
+            };
+
+            finished.push(match &job.state {
+                JobState::Done => match job.progress.skipped() {
+                    0 => format!("Finished: {}", job.description),
+                    skipped => format!("Finished: {}, skipped {} item(s) that can't be copied", job.description, skipped),
+                },
+                JobState::Cancelled => format!("Cancelled: {}", job.description),
+                JobState::Failed(e) => format!("Failed: {}: {}", job.description, e),
+                _ => String::new(),
+            });
+        }
+
+        finished
+    }
+
+    pub fn cancel(&mut self, id: usize) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id && job.is_active()) {
+            job.progress.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn toggle_pause(&mut self, id: usize) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id && job.is_active()) {
+            if job.state == JobState::Paused {
+                job.paused_for += job.paused_at.take().map(|at| at.elapsed()).unwrap_or_default();
+                job.state = JobState::Running;
+            } else {
+                job.paused_at = Some(Instant::now());
+                job.state = JobState::Paused;
+            }
+            job.progress.paused.store(job.state == JobState::Paused, Ordering::Relaxed);
+        }
+    }
+
+    /// Drops finished, failed and cancelled jobs from the list
+    pub fn clear_finished(&mut self) {
+        self.jobs.retain(|job| job.is_active());
+    }
+
+    pub fn active(&self) -> usize {
+        self.jobs.iter().filter(|job| job.is_active()).count()
+    }
+}
@@ -0,0 +1,597 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Deserializer};
+
+/// A key with its modifiers, written like `ctrl+r`, `shift+tab`, `alt+left` or `G`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    /// The key a terminal reported. Shift is already part of a typed char, so it's dropped for those.
+    pub fn of(event: &KeyEvent) -> Key {
+        let modifiers = match event.code {
+            KeyCode::Char(_) | KeyCode::BackTab => event.modifiers - KeyModifiers::SHIFT,
+            _ => event.modifiers,
+        };
+
+        Key { code: event.code, modifiers }
+    }
+
+    pub fn parse(text: &str) -> Option<Key> {
+        // `+` on its own, or at the end of a combination, is the plus key
+        let (prefix, name) = match text.strip_suffix('+') {
+            Some(rest) if rest.is_empty() || rest.ends_with('+') => (rest.trim_end_matches('+'), "+"),
+            _ => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in prefix.split('+').filter(|modifier| !modifier.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return None,
+            };
+        }
+
+        let mut chars = name.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match name.to_lowercase().as_str() {
+                "enter" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "space" => KeyCode::Char(' '),
+                function => KeyCode::F(function.strip_prefix('f')?.parse().ok()?),
+            },
+        };
+
+        // Written as `shift+s`, typed as `S`
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::Char(c.to_ascii_uppercase()),
+            code => code,
+        };
+
+        Some(Key::of(&KeyEvent::new(code, modifiers)))
+    }
+
+    /// How the key is shown in hints, e.g. `Ctrl+R` or `Space`.
+    pub fn label(&self) -> String {
+        let name = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) if self.modifiers.is_empty() => c.to_string(),
+            KeyCode::Char(c) => c.to_ascii_uppercase().to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            KeyCode::BackTab => "Shift+Tab".to_string(),
+            KeyCode::Backspace => "Backspace".to_string(),
+            KeyCode::PageUp => "PgUp".to_string(),
+            KeyCode::PageDown => "PgDn".to_string(),
+            code => format!("{:?}", code),
+        };
+
+        let mut label = String::new();
+        for (modifier, prefix) in [(KeyModifiers::CONTROL, "Ctrl+"), (KeyModifiers::ALT, "Alt+"), (KeyModifiers::SHIFT, "Shift+")] {
+            if self.modifiers.contains(modifier) {
+                label.push_str(prefix);
+            }
+        }
+
+        label + &name
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Key, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Key::parse(&text).ok_or_else(|| serde::de::Error::custom(format!("unknown key: {}", text)))
+    }
+}
+
+/// Keys pressed one after another, written separated by spaces like `g g` or `ctrl+x ctrl+c`.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct KeySequence(pub Vec<Key>);
+
+impl KeySequence {
+    pub fn parse(text: &str) -> Option<KeySequence> {
+        let keys = text.split_whitespace().map(Key::parse).collect::<Option<Vec<Key>>>()?;
+        (!keys.is_empty()).then_some(KeySequence(keys))
+    }
+
+    /// Plain letters run together like `gg`, anything else is spaced out like `Ctrl+X Ctrl+C`.
+    pub fn label(&self) -> String {
+        let plain = self.0.len() > 1 && self.0.iter().all(|key| matches!(key.code, KeyCode::Char(c) if c != ' ') && key.modifiers.is_empty());
+        let labels: Vec<String> = self.0.iter().map(Key::label).collect();
+
+        labels.join(if plain { "" } else { " " })
+    }
+}
+
+impl<'de> Deserialize<'de> for KeySequence {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<KeySequence, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        KeySequence::parse(&text).ok_or_else(|| serde::de::Error::custom(format!("unknown key: {}", text)))
+    }
+}
+
+/// Something that can be done from the listing, whatever key it's bound to.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Quit,
+    Palette,
+    Up,
+    Down,
+    Top,
+    Bottom,
+    Enter,
+    Parent,
+    Back,
+    Forward,
+    SetBookmark,
+    JumpToBookmark,
+    ShowBookmarks,
+    Jump,
+    Open,
+    ToggleMark,
+    MarkAll,
+    InvertMarks,
+    MarkGlob,
+    ClearMarks,
+    Search,
+    FindInFiles,
+    NewFile,
+    Symlink,
+    HardLink,
+    Permissions,
+    Undo,
+    Redo,
+    Rename,
+    BulkRename,
+    PatternRename,
+    Trash,
+    DeletePermanently,
+    ShowTrash,
+    ShowJobs,
+    Copy,
+    Cut,
+    Paste,
+    Reindex,
+    ReloadConfig,
+    SortByName,
+    SortBySize,
+    SortByModified,
+    SortByPermissions,
+    SortByType,
+}
+
+impl Action {
+    /// The order actions are listed in the bottom bar and the command palette, most used first
+    pub const HINTS: [Action; 45] = [
+        Action::Palette,
+        Action::Up,
+        Action::Down,
+        Action::NewFile,
+        Action::Copy,
+        Action::Cut,
+        Action::Paste,
+        Action::Rename,
+        Action::Trash,
+        Action::Search,
+        Action::FindInFiles,
+        Action::Open,
+        Action::ToggleMark,
+        Action::Undo,
+        Action::Redo,
+        Action::Enter,
+        Action::Parent,
+        Action::Back,
+        Action::Forward,
+        Action::SetBookmark,
+        Action::JumpToBookmark,
+        Action::ShowBookmarks,
+        Action::Jump,
+        Action::Top,
+        Action::Bottom,
+        Action::Symlink,
+        Action::HardLink,
+        Action::BulkRename,
+        Action::PatternRename,
+        Action::DeletePermanently,
+        Action::ShowTrash,
+        Action::ShowJobs,
+        Action::Permissions,
+        Action::MarkAll,
+        Action::InvertMarks,
+        Action::MarkGlob,
+        Action::Reindex,
+        Action::ReloadConfig,
+        Action::SortByName,
+        Action::SortBySize,
+        Action::SortByModified,
+        Action::SortByPermissions,
+        Action::SortByType,
+        Action::ClearMarks,
+        Action::Quit,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::Palette => "Commands",
+            Action::Up => "Up",
+            Action::Down => "Down",
+            Action::Top => "Top",
+            Action::Bottom => "Bottom",
+            Action::Enter => "Enter Folder",
+            Action::Parent => "Up a Folder",
+            Action::Back => "Back",
+            Action::Forward => "Forward",
+            Action::SetBookmark => "Bookmark",
+            Action::JumpToBookmark => "Go to Bookmark",
+            Action::ShowBookmarks => "Bookmarks",
+            Action::Jump => "Jump",
+            Action::Open => "Open",
+            Action::ToggleMark => "Mark",
+            Action::MarkAll => "All",
+            Action::InvertMarks => "Invert",
+            Action::MarkGlob => "Glob",
+            Action::ClearMarks => "Unmark",
+            Action::Search => "Search",
+            Action::FindInFiles => "Find in Files",
+            Action::NewFile => "Create",
+            Action::Symlink => "Symlink",
+            Action::HardLink => "Hard Link",
+            Action::Permissions => "Permissions",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::Rename => "Rename",
+            Action::BulkRename => "Bulk Rename",
+            Action::PatternRename => "Pattern Rename",
+            Action::Trash => "Trash",
+            Action::DeletePermanently => "Delete",
+            Action::ShowTrash => "Trash Bin",
+            Action::ShowJobs => "Jobs",
+            Action::Copy => "Copy",
+            Action::Cut => "Cut",
+            Action::Paste => "Paste",
+            Action::Reindex => "Reindex",
+            Action::ReloadConfig => "Reload Config",
+            Action::SortByName => "Sort Name",
+            Action::SortBySize => "Sort Size",
+            Action::SortByModified => "Sort Modified",
+            Action::SortByPermissions => "Sort Permissions",
+            Action::SortByType => "Sort Type",
+        }
+    }
+}
+
+/// A built-in set of bindings to start from.
+#[derive(Clone, Copy, PartialEq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Default,
+    Vim,
+    Emacs,
+}
+
+const DEFAULT: &[(&str, Action)] = &[
+    ("q", Action::Quit),
+    (":", Action::Palette),
+    ("ctrl+p", Action::Palette),
+    ("up", Action::Up),
+    ("down", Action::Down),
+    ("home", Action::Top),
+    ("end", Action::Bottom),
+    ("enter", Action::Enter),
+    ("backspace", Action::Parent),
+    ("H", Action::Back),
+    ("alt+left", Action::Back),
+    ("L", Action::Forward),
+    ("alt+right", Action::Forward),
+    ("m", Action::SetBookmark),
+    ("'", Action::JumpToBookmark),
+    ("b", Action::ShowBookmarks),
+    ("z", Action::Jump),
+    ("o", Action::Open),
+    ("space", Action::ToggleMark),
+    ("a", Action::MarkAll),
+    ("*", Action::InvertMarks),
+    ("+", Action::MarkGlob),
+    ("esc", Action::ClearMarks),
+    ("/", Action::Search),
+    ("f", Action::FindInFiles),
+    ("n", Action::NewFile),
+    ("s", Action::Symlink),
+    ("S", Action::HardLink),
+    ("p", Action::Permissions),
+    ("u", Action::Undo),
+    ("ctrl+r", Action::Redo),
+    ("r", Action::Rename),
+    ("R", Action::BulkRename),
+    ("%", Action::PatternRename),
+    ("d", Action::Trash),
+    ("D", Action::DeletePermanently),
+    ("t", Action::ShowTrash),
+    ("J", Action::ShowJobs),
+    ("c", Action::Copy),
+    ("x", Action::Cut),
+    ("v", Action::Paste),
+    ("I", Action::Reindex),
+    ("f5", Action::ReloadConfig),
+    ("1", Action::SortByName),
+    ("2", Action::SortBySize),
+    ("3", Action::SortByModified),
+    ("4", Action::SortByPermissions),
+    ("5", Action::SortByType),
+];
+
+// Modelled on ranger
+const VIM: &[(&str, Action)] = &[
+    ("q", Action::Quit),
+    (":", Action::Palette),
+    ("k", Action::Up),
+    ("up", Action::Up),
+    ("j", Action::Down),
+    ("down", Action::Down),
+    ("g g", Action::Top),
+    ("G", Action::Bottom),
+    ("l", Action::Enter),
+    ("enter", Action::Enter),
+    ("h", Action::Parent),
+    ("backspace", Action::Parent),
+    ("H", Action::Back),
+    ("alt+left", Action::Back),
+    ("L", Action::Forward),
+    ("alt+right", Action::Forward),
+    ("m", Action::SetBookmark),
+    ("'", Action::JumpToBookmark),
+    ("`", Action::JumpToBookmark),
+    ("g b", Action::ShowBookmarks),
+    ("z", Action::Jump),
+    ("r", Action::Open),
+    ("space", Action::ToggleMark),
+    ("V", Action::MarkAll),
+    ("v", Action::InvertMarks),
+    ("+", Action::MarkGlob),
+    ("esc", Action::ClearMarks),
+    ("/", Action::Search),
+    ("f", Action::FindInFiles),
+    ("n", Action::NewFile),
+    ("s", Action::Symlink),
+    ("S", Action::HardLink),
+    ("=", Action::Permissions),
+    ("u", Action::Undo),
+    ("ctrl+r", Action::Redo),
+    ("c w", Action::Rename),
+    ("R", Action::BulkRename),
+    ("%", Action::PatternRename),
+    ("d D", Action::Trash),
+    ("D D", Action::DeletePermanently),
+    ("g t", Action::ShowTrash),
+    ("w", Action::ShowJobs),
+    ("y y", Action::Copy),
+    ("d d", Action::Cut),
+    ("p p", Action::Paste),
+    ("I", Action::Reindex),
+    ("f5", Action::ReloadConfig),
+    ("o n", Action::SortByName),
+    ("o s", Action::SortBySize),
+    ("o m", Action::SortByModified),
+    ("o p", Action::SortByPermissions),
+    ("o t", Action::SortByType),
+];
+
+// Modelled on dired
+const EMACS: &[(&str, Action)] = &[
+    ("ctrl+x ctrl+c", Action::Quit),
+    ("alt+x", Action::Palette),
+    ("ctrl+p", Action::Up),
+    ("up", Action::Up),
+    ("ctrl+n", Action::Down),
+    ("down", Action::Down),
+    ("alt+<", Action::Top),
+    ("alt+>", Action::Bottom),
+    ("enter", Action::Enter),
+    ("^", Action::Parent),
+    ("backspace", Action::Parent),
+    ("l", Action::Back),
+    ("alt+left", Action::Back),
+    ("r", Action::Forward),
+    ("alt+right", Action::Forward),
+    ("ctrl+x r m", Action::SetBookmark),
+    ("ctrl+x r b", Action::JumpToBookmark),
+    ("ctrl+x r l", Action::ShowBookmarks),
+    ("j", Action::Jump),
+    ("o", Action::Open),
+    ("m", Action::ToggleMark),
+    ("* s", Action::MarkAll),
+    ("t", Action::InvertMarks),
+    ("% m", Action::MarkGlob),
+    ("U", Action::ClearMarks),
+    ("ctrl+g", Action::ClearMarks),
+    ("ctrl+s", Action::Search),
+    ("alt+g", Action::FindInFiles),
+    ("+", Action::NewFile),
+    ("S", Action::Symlink),
+    ("H", Action::HardLink),
+    ("M", Action::Permissions),
+    ("ctrl+x u", Action::Undo),
+    ("alt+_", Action::Redo),
+    ("R", Action::Rename),
+    ("ctrl+x ctrl+q", Action::BulkRename),
+    ("% r", Action::PatternRename),
+    ("D", Action::Trash),
+    ("alt+D", Action::DeletePermanently),
+    ("ctrl+x t", Action::ShowTrash),
+    ("ctrl+x j", Action::ShowJobs),
+    ("alt+w", Action::Copy),
+    ("ctrl+w", Action::Cut),
+    ("ctrl+y", Action::Paste),
+    ("ctrl+x i", Action::Reindex),
+    ("f5", Action::ReloadConfig),
+    ("s n", Action::SortByName),
+    ("s s", Action::SortBySize),
+    ("s m", Action::SortByModified),
+    ("s p", Action::SortByPermissions),
+    ("s t", Action::SortByType),
+];
+
+/// What a run of keys comes to.
+pub enum Lookup {
+    Action(Action),
+    /// The start of a longer binding, so wait for the next key
+    Pending,
+    None,
+}
+
+/// The keys bound to each action in the listing.
+#[derive(Clone)]
+pub struct Keymap {
+    bindings: Vec<(KeySequence, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::new(Preset::Default, &HashMap::new())
+    }
+}
+
+impl Keymap {
+    /// Starts from a preset and applies the config's own bindings over it.
+    pub fn new(preset: Preset, bindings: &HashMap<KeySequence, Action>) -> Keymap {
+        let table = match preset {
+            Preset::Default => DEFAULT,
+            Preset::Vim => VIM,
+            Preset::Emacs => EMACS,
+        };
+
+        let mut keymap = Keymap { bindings: Vec::new() };
+        for (keys, action) in table {
+            keymap.bind(KeySequence::parse(keys).expect("preset keys are valid"), *action);
+        }
+        for (keys, action) in bindings {
+            keymap.bind(keys.clone(), *action);
+        }
+
+        keymap
+    }
+
+    /// Binds a sequence, dropping any binding it would shadow or be shadowed by, so `d d` replaces `d`.
+    pub fn bind(&mut self, keys: KeySequence, action: Action) {
+        self.bindings.retain(|(bound, _)| !bound.0.starts_with(&keys.0) && !keys.0.starts_with(&bound.0));
+        self.bindings.push((keys, action));
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        let mut pending = false;
+
+        for (bound, action) in &self.bindings {
+            if bound.0 == keys {
+                return Lookup::Action(*action);
+            }
+            pending |= bound.0.starts_with(keys);
+        }
+
+        match pending {
+            true => Lookup::Pending,
+            false => Lookup::None,
+        }
+    }
+
+    /// The first binding for an action, as shown in hints.
+    pub fn keys_for(&self, action: Action) -> Option<&KeySequence> {
+        self.bindings.iter().find(|(_, bound)| *bound == action).map(|(keys, _)| keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        Key { code, modifiers }
+    }
+
+    fn sequence(text: &str) -> KeySequence {
+        KeySequence::parse(text).unwrap()
+    }
+
+    fn action(keymap: &Keymap, text: &str) -> Option<Action> {
+        match keymap.lookup(&sequence(text).0) {
+            Lookup::Action(action) => Some(action),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert!(Key::parse("ctrl+r") == Some(key(KeyCode::Char('r'), KeyModifiers::CONTROL)));
+        assert!(Key::parse("Ctrl+Alt+left") == Some(key(KeyCode::Left, KeyModifiers::CONTROL | KeyModifiers::ALT)));
+        assert!(Key::parse("shift+s") == Key::parse("S"));
+        // Shift is part of BackTab, the way terminals report it
+        assert!(Key::parse("shift+tab") == Some(key(KeyCode::BackTab, KeyModifiers::NONE)));
+        assert!(Key::parse("space") == Some(key(KeyCode::Char(' '), KeyModifiers::NONE)));
+        assert!(Key::parse("f5") == Some(key(KeyCode::F(5), KeyModifiers::NONE)));
+        assert!(Key::parse("+") == Some(key(KeyCode::Char('+'), KeyModifiers::NONE)));
+        assert!(Key::parse("ctrl++") == Some(key(KeyCode::Char('+'), KeyModifiers::CONTROL)));
+        assert!(Key::parse("hyper+a").is_none());
+        assert!(Key::parse("nope").is_none());
+    }
+
+    #[test]
+    fn parses_sequences() {
+        assert_eq!(sequence("g g").0.len(), 2);
+        assert!(sequence("ctrl+x ctrl+c").0.iter().all(|key| key.modifiers == KeyModifiers::CONTROL));
+        assert!(KeySequence::parse("").is_none());
+        assert!(KeySequence::parse("g nope").is_none());
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(sequence("g g").label(), "gg");
+        assert_eq!(sequence("ctrl+x ctrl+c").label(), "Ctrl+X Ctrl+C");
+        assert_eq!(sequence("space").label(), "Space");
+        assert_eq!(sequence("shift+tab").label(), "Shift+Tab");
+    }
+
+    #[test]
+    fn prefixes_wait_for_more_keys() {
+        let keymap = Keymap::new(Preset::Vim, &HashMap::new());
+
+        assert!(matches!(keymap.lookup(&sequence("g").0), Lookup::Pending));
+        assert!(action(&keymap, "g g") == Some(Action::Top));
+        assert!(matches!(keymap.lookup(&sequence("g x").0), Lookup::None));
+    }
+
+    #[test]
+    fn bindings_replace_what_they_shadow() {
+        let mut bindings = HashMap::new();
+        bindings.insert(sequence("d d"), Action::Trash);
+        bindings.insert(sequence("g"), Action::Parent);
+        let keymap = Keymap::new(Preset::Default, &bindings);
+
+        // `d d` drops the preset's `d`, and `g` drops every `g ...`
+        assert!(matches!(keymap.lookup(&sequence("d").0), Lookup::Pending));
+        assert!(action(&keymap, "d d") == Some(Action::Trash));
+        assert!(action(&keymap, "g") == Some(Action::Parent));
+        assert!(keymap.keys_for(Action::Trash).is_some_and(|keys| keys.label() == "dd"));
+    }
+}
@@ -1,24 +1,28 @@
 use crossterm::{
-    event::{self,  Event as CEvent, KeyCode},
+    event::{self,  Event as CEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     terminal::{disable_raw_mode, enable_raw_mode},
 };
-use serde::{Deserialize, Serialize};
-use std::{env::current_dir, fs, path};
 use std::io;
-use std::path::{Path, PathBuf};
-use std::sync::mpsc;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
 use std::thread;
 use std::time::{Duration, Instant};
 use thiserror::Error;
-use tui::{
-    backend::{Backend, CrosstermBackend}, layout::{Alignment, Constraint, Direction, Layout, Margin}, style::{Color, Modifier, Style}, symbols::line::VERTICAL, text::{Span, Spans}, widgets::{
-        Block, BorderType, Borders, Cell, List, ListItem, ListState, Paragraph, Row, Table, Tabs,
-    }, Terminal,Frame
-};
+use tui::{backend::CrosstermBackend, widgets::{ListState, TableState}, Terminal};
+
+use keymap::{Action, Key, Lookup};
 
 
 mod filesystem;
 mod draw;
+mod cli;
+mod jobs;
+mod fuzzy;
+mod config;
+mod keymap;
+mod palette;
+#[allow(non_snake_case)]
 mod AppInfo;
 
 
@@ -28,66 +32,174 @@ pub enum Error{
     ReadDBError(#[from] io::Error),
     #[error("error parsing the DB file: {0}")]
     ParseDBError(#[from] serde_json::Error),
+    #[error("error reading the config file: {0}")]
+    ReadConfigError(io::Error),
+    #[error("error parsing the config file: {0}")]
+    ParseConfigError(#[from] toml::de::Error),
 }
 
 enum Event<I> {
     Input(I),
     Tick,
+    // Something changed in the directory being shown
+    Changed,
 }
 
 
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    enable_raw_mode().expect("can run in raw mode");
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let config_given = match cli::take_config(&mut args) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("{}\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        },
+    };
+
+    match cli::parse_batch(&args) {
+        Ok(Some(batch)) => {
+            if !cli::run_batch(batch) {
+                std::process::exit(1);
+            }
+            return Ok(());
+        },
+        Ok(None) => {},
+        Err(e) => {
+            eprintln!("{}\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        },
+    }
+
+    // Only a file named with `--config` has to exist
+    let config_required = config_given.is_some();
+    let config_path = config_given.map(std::path::PathBuf::from).unwrap_or_else(config::default_path);
+    let mut config = match config::load(&config_path, config_required) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("{}: {}", config_path.display(), e);
+            std::process::exit(2);
+        },
+    };
 
-    let mut app = AppInfo::App::default();
+    let start_directory = match cli::start_directory(&args, &config) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("{}\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        },
+    };
+    config.resolve(&start_directory);
 
-    app.loaded_files = filesystem::util::fill_hashmap(".").unwrap(); //IMPORANT! CHANGE BACK TO DRIVE
+    enable_raw_mode().expect("can run in raw mode");
 
-    app.current_directory = "C:/Users/XxAnd/Documents".to_string();
-    app.selected_file = "".to_string();
+    let mut app = AppInfo::App {
+        current_directory: start_directory.clone(),
+        selected_file: "".to_string(),
+        sort: config.sort,
+        keymap: config.keymap.keymap(),
+        config,
+        config_path,
+        config_required,
+        start_directory,
+        ..Default::default()
+    };
 
     let (tx, rx) = mpsc::channel();
-    let tick_rate = Duration::from_millis(200);
+    // Shared with the input thread so a reloaded config can change it
+    let tick_rate = Arc::new(AtomicU64::new(app.config.tick_rate));
+    let ticks = Arc::clone(&tick_rate);
+    let input_paused = Arc::new(AtomicBool::new(false));
+    let paused = Arc::clone(&input_paused);
+    let watch_tx = tx.clone();
 
     thread::spawn(move || {
         let mut last_tick = Instant::now();
-        let mut last_key_time = Instant::now();
 
         loop{
+            let tick_rate = Duration::from_millis(ticks.load(Ordering::Relaxed).max(10));
+
+            // An external program has the terminal, so leave its keys alone
+            if paused.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(50));
+                continue;
+            }
+
             let timeout = tick_rate
                 .checked_sub(last_tick.elapsed())
                 .unwrap_or_else(|| Duration::from_secs(0));
 
             if event::poll(timeout).expect("poll works"){
+                // Some terminals also report releases, which would act on every key twice
                 if let CEvent::Key(key) = event::read().expect("can read events"){
-                    let now = Instant::now();
-                    let elapsed = now - last_key_time;
-
-                    if elapsed >= tick_rate {
-                        last_key_time = now;
+                    if key.kind != KeyEventKind::Release {
                         tx.send(Event::Input(key)).expect("can send events");
-                    }}
+                    }
+                }
             }
 
-            if last_tick.elapsed() >= tick_rate {
-                if let Ok(_) = tx.send(Event::Tick){
-                    last_tick = Instant::now();
-                }
+            if last_tick.elapsed() >= tick_rate && tx.send(Event::Tick).is_ok(){
+                last_tick = Instant::now();
             }
         }
     });
 
+    // Only one change event is queued at a time, so a burst of changes means one reload rather than hundreds
+    let changed = Arc::new(AtomicBool::new(false));
+    let pending = Arc::clone(&changed);
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            if !matches!(event.kind, notify::EventKind::Access(_)) && !pending.swap(true, Ordering::Relaxed) {
+                let _ = watch_tx.send(Event::Changed);
+            }
+        }
+    });
+    if let Err(e) = &watcher {
+        app.status = format!("Changes won't show up live: {}", e);
+    }
+    let mut watched = String::new();
+
     let stdout = io::stdout();
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
+    // Start from the saved index and bring it up to date in the background
+    match filesystem::index::load() {
+        Ok(index) => {
+            app.index = Arc::new(RwLock::new(index));
+            refresh_index(&mut app, false);
+        },
+        Err(Error::ReadDBError(e)) if e.kind() == io::ErrorKind::NotFound => refresh_index(&mut app, true),
+        Err(e) => {
+            app.status = format!("Rebuilding the file index: {}", e);
+            refresh_index(&mut app, true);
+        },
+    }
+
+    match filesystem::frecency::load() {
+        Ok(frecency) => app.frecency = frecency,
+        Err(e) => app.status = format!("Could not read the visited folders: {}", e),
+    }
+
+    match filesystem::bookmarks::load() {
+        Ok(bookmarks) => app.bookmarks = bookmarks,
+        Err(e) => app.status = format!("Could not read the bookmarks: {}", e),
+    }
+
     app.active_menu_item = AppInfo::MenuItem::Home;
     app.directory_list_state.select(Some(0));
     app.search_list_state.select(Some(0));
+    app.jobs_list_state.select(Some(0));
+    app.bookmarks_list_state.select(Some(0));
 
     loop{
+        if app.current_directory != watched {
+            follow_directory(&mut app, watcher.as_mut().ok(), &watched);
+            watched = app.current_directory.clone();
+        }
+
         //Main Rendering
         let _ = terminal.draw(|f| draw::draw_ui(f, &mut app));
     
@@ -95,110 +207,488 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         match rx.recv()? {
             Event::Input(event) => {
                 match app.input_mode {
-                    AppInfo::InputMode::Normal => {
-                        // Your existing match statements for normal mode
+                    AppInfo::InputMode::Normal if app.active_menu_item == AppInfo::MenuItem::Trash => {
                         match event.code {
+                            KeyCode::Char('q') if app.jobs.active() > 0 && !app.quit_pending => {
+                                app.quit_pending = true;
+                                app.status = format!("{} job(s) still running. Press q again to cancel them and quit", app.jobs.active());
+                            }
+
                             KeyCode::Char('q') => {
                                 disable_raw_mode()?;
                                 terminal.show_cursor()?;
                                 break;
                             }
-                            
+
                             KeyCode::Up => {
-                                if let Some(selected) = app.directory_list_state.selected() {
-                                    let amount_pets = filesystem::util::get_files_in_directory(&app.current_directory).unwrap().len(); // Move to Util
-                    
-                                    if selected > 0{
-                                        app.directory_list_state.select(Some(selected -1));
-                                    }else{
-                                        app.directory_list_state.select(Some(amount_pets -1));
-                                    }
-                                }
+                                step_selection(&mut app.trash_list_state, app.trash_entries.len(), false);
                             }
 
                             KeyCode::Down => {
-                                if let Some(selected) = app.directory_list_state.selected() {
-                                    let amount_pets = filesystem::util::get_files_in_directory(&app.current_directory).unwrap().len(); // Move to Util
-                    
-                                    if selected >= amount_pets -1{
-                                        app.directory_list_state.select(Some(0));
-                                    }else{
-                                        app.directory_list_state.select(Some(selected+1));
-                                    }
+                                step_selection(&mut app.trash_list_state, app.trash_entries.len(), true);
+                            }
+
+                            KeyCode::Enter | KeyCode::Char('r') => {
+                                // Restoring can copy a whole folder back across filesystems, so it runs as a job and is journaled
+                                if let Some(index) = app.trash_list_state.selected().filter(|i| *i < app.trash_entries.len()) {
+                                    let entry = app.trash_entries.remove(index);
+
+                                    app.jobs.spawn(format!("Restore {}", entry.original_path), move |progress| {
+                                        let (bytes, files) = filesystem::util::measure(&entry.trashed_path);
+                                        progress.add_total(bytes, files);
+                                        filesystem::explorer::restore_file(&entry, progress)
+                                    });
                                 }
                             }
-                            
 
-                            KeyCode::Char('o') =>{
-                                let _ = filesystem::explorer::open_file(app.selected_file.clone());
+                            KeyCode::Char('D') => {
+                                if let Some(index) = app.trash_list_state.selected().filter(|i| *i < app.trash_entries.len()) {
+                                    let entry = app.trash_entries.remove(index);
+
+                                    app.jobs.spawn(format!("Delete {} permanently", entry.name), move |progress| {
+                                        let (bytes, files) = filesystem::util::measure(&entry.trashed_path);
+                                        progress.add_total(bytes, files);
+                                        filesystem::trash::purge(&entry, progress)
+                                    });
+                                }
                             }
 
-                            KeyCode::Char('/') => {
-                                app.active_menu_item = AppInfo::MenuItem::Text;
-                                app.input_mode = AppInfo::InputMode::Typing;
+                            KeyCode::Char('E') => {
+                                begin_input(&mut app, AppInfo::InputType::EmptyTrash);
+                            }
 
-                                app.input_type = AppInfo::InputType::Searching;
+                            KeyCode::Esc | KeyCode::Char('t') => {
+                                app.active_menu_item = AppInfo::MenuItem::Home;
                             }
 
-                            KeyCode::Char('n') => {
-                                app.active_menu_item = AppInfo::MenuItem::Text;
-                                app.input_mode = AppInfo::InputMode::Typing;
-                                app.input_type = AppInfo::InputType::MakeFile;
+                            _ => {}
+                        }
+                    }
+                    AppInfo::InputMode::Normal if app.active_menu_item == AppInfo::MenuItem::Bookmarks => {
+                        let selected = app.bookmarks_list_state.selected().filter(|i| *i < app.bookmarks.len());
+
+                        match event.code {
+                            KeyCode::Up => {
+                                step_selection(&mut app.bookmarks_list_state, app.bookmarks.len(), false);
+                            }
 
+                            KeyCode::Down => {
+                                step_selection(&mut app.bookmarks_list_state, app.bookmarks.len(), true);
+                            }
 
+                            KeyCode::Enter => {
+                                if let Some(index) = selected {
+                                    let mark = app.bookmarks[index].mark;
+                                    app.active_menu_item = AppInfo::MenuItem::Home;
+                                    jump_to_bookmark(&mut app, mark);
+                                }
                             }
 
                             KeyCode::Char('r') => {
-                                app.active_menu_item = AppInfo::MenuItem::Text;
-                                app.input_mode = AppInfo::InputMode::Typing;
-                                app.input_type = AppInfo::InputType::RenameFile;
+                                if let Some(index) = selected {
+                                    app.input = app.bookmarks[index].name.clone();
+                                    begin_input(&mut app, AppInfo::InputType::RenameBookmark);
+                                }
+                            }
 
+                            KeyCode::Char('d') => {
+                                if let Some(index) = selected {
+                                    let bookmark = app.bookmarks.remove(index);
+                                    save_bookmarks(&mut app, format!("Deleted bookmark {}", bookmark.mark));
+                                    step_selection(&mut app.bookmarks_list_state, app.bookmarks.len(), false);
+                                }
+                            }
 
+                            KeyCode::Esc => {
+                                app.active_menu_item = AppInfo::MenuItem::Home;
                             }
 
-                            KeyCode::Char('d') => {
-                                let _ = filesystem::explorer::delete_file(&app.selected_file);
+                            _ => {}
+                        }
+                    }
+                    AppInfo::InputMode::Normal if app.active_menu_item == AppInfo::MenuItem::Jobs => {
+                        let selected = app.jobs_list_state.selected().and_then(|i| app.jobs.jobs.get(i)).map(|job| job.id);
+
+                        match event.code {
+                            KeyCode::Up => {
+                                step_selection(&mut app.jobs_list_state, app.jobs.jobs.len(), false);
+                            }
 
+                            KeyCode::Down => {
+                                step_selection(&mut app.jobs_list_state, app.jobs.jobs.len(), true);
                             }
 
                             KeyCode::Char('c') => {
-                                let _ = filesystem::explorer::copy_file_to_cache(&app.selected_file);
+                                if let Some(id) = selected {
+                                    app.jobs.cancel(id);
+                                }
+                            }
+
+                            KeyCode::Char('p') => {
+                                if let Some(id) = selected {
+                                    app.jobs.toggle_pause(id);
+                                }
+                            }
+
+                            KeyCode::Char('x') => {
+                                app.jobs.clear_finished();
+                                app.jobs_list_state.select(Some(0));
+                            }
+
+                            KeyCode::Esc | KeyCode::Char('J') => {
+                                app.active_menu_item = AppInfo::MenuItem::Home;
+                            }
+
+                            _ => {}
+                        }
+                    }
+                    AppInfo::InputMode::Normal if app.active_menu_item == AppInfo::MenuItem::Conflict => {
+                        match event.code {
+                            KeyCode::Char('o') => resolve_conflict(&mut app, filesystem::conflict::Resolution::Overwrite),
+                            KeyCode::Char('s') => resolve_conflict(&mut app, filesystem::conflict::Resolution::Skip),
+                            KeyCode::Char('k') => resolve_conflict(&mut app, filesystem::conflict::Resolution::KeepBoth),
+
+                            KeyCode::Char('r') => {
+                                if let Some(operation) = app.operations.pending.front() {
+                                    app.input = filesystem::util::file_name(&filesystem::conflict::keep_both_path(&operation.destination, &app.operations.planned()).to_string_lossy()).unwrap_or_default();
+                                }
+                                begin_input(&mut app, AppInfo::InputType::ConflictRename);
+                            }
+
+                            KeyCode::Char('a') => {
+                                app.operations.apply_to_all = !app.operations.apply_to_all;
+                            }
+
+                            KeyCode::Esc => {
+                                // Nothing in the batch has run yet, overwrites included, so all of it is dropped
+                                let cancelled = app.operations.pending.len() + app.operations.ready.len();
+                                app.operations.pending.clear();
+                                app.rename_queue.clear();
+                                finish_pending(&mut app);
+                                app.status = format!("Cancelled {} operation(s), nothing was changed", cancelled);
+                            }
+
+                            _ => {}
+                        }
+                    }
+                    AppInfo::InputMode::Normal if app.active_menu_item == AppInfo::MenuItem::Permissions => {
+                        let dialog = match app.permissions.as_mut() {
+                            Some(data) => data,
+                            None => {
+                                app.active_menu_item = AppInfo::MenuItem::Home;
+                                continue;
+                            }
+                        };
+
+                        match (dialog.focus, event.code) {
+                            (_, KeyCode::Esc) => {
+                                app.permissions = None;
+                                app.active_menu_item = AppInfo::MenuItem::Home;
+                            }
+
+                            (_, KeyCode::Enter) => match dialog.change() {
+                                Ok(change) => {
+                                    let targets = dialog.targets.clone();
+                                    let recursive = dialog.recursive;
+
+                                    app.jobs.spawn(format!("Change permissions of {} item(s)", targets.len()), move |progress| {
+                                        filesystem::permissions::apply(&targets, &change, recursive, progress)
+                                    });
+                                    app.permissions = None;
+                                    app.marked.clear();
+                                    app.active_menu_item = AppInfo::MenuItem::Home;
+                                },
+                                Err(e) => app.status = format!("Could not change permissions: {}", e),
+                            },
+
+                            (_, KeyCode::Tab) => {
+                                dialog.focus = match dialog.focus {
+                                    AppInfo::PermissionsFocus::Checkboxes => AppInfo::PermissionsFocus::Octal,
+                                    AppInfo::PermissionsFocus::Octal => AppInfo::PermissionsFocus::Owner,
+                                    AppInfo::PermissionsFocus::Owner => AppInfo::PermissionsFocus::Checkboxes,
+                                };
+                            }
+
+                            (_, KeyCode::Char('r')) if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                                dialog.recursive = !dialog.recursive;
+                            }
+
+                            (AppInfo::PermissionsFocus::Checkboxes, KeyCode::Left) => dialog.cursor = (dialog.cursor + 11) % 12,
+                            (AppInfo::PermissionsFocus::Checkboxes, KeyCode::Right) => dialog.cursor = (dialog.cursor + 1) % 12,
+                            (AppInfo::PermissionsFocus::Checkboxes, KeyCode::Up) => dialog.cursor = (dialog.cursor + 9) % 12,
+                            (AppInfo::PermissionsFocus::Checkboxes, KeyCode::Down) => dialog.cursor = (dialog.cursor + 3) % 12,
+                            (AppInfo::PermissionsFocus::Checkboxes, KeyCode::Char(' ')) => dialog.toggle(),
+
+                            (AppInfo::PermissionsFocus::Octal, KeyCode::Char(c)) if c.is_digit(8) && dialog.octal.len() < 4 => {
+                                let octal = format!("{}{}", dialog.octal, c);
+                                dialog.edit_octal(octal);
+                            }
+                            (AppInfo::PermissionsFocus::Octal, KeyCode::Backspace) => {
+                                let mut octal = dialog.octal.clone();
+                                octal.pop();
+                                dialog.edit_octal(octal);
+                            }
+
+                            (AppInfo::PermissionsFocus::Owner, KeyCode::Char(c)) => dialog.owner.push(c),
+                            (AppInfo::PermissionsFocus::Owner, KeyCode::Backspace) => {
+                                dialog.owner.pop();
+                            }
+
+                            _ => {}
+                        }
+                    }
+                    AppInfo::InputMode::Normal if app.active_menu_item == AppInfo::MenuItem::RenamePreview => {
+                        let lines = app.rename_plan.as_ref().map(|plan| plan.problems.len() + plan.changes.len()).unwrap_or(0);
+
+                        match event.code {
+                            KeyCode::Up => {
+                                step_selection(&mut app.rename_list_state, lines, false);
+                            }
+
+                            KeyCode::Down => {
+                                step_selection(&mut app.rename_list_state, lines, true);
+                            }
+
+                            KeyCode::Enter | KeyCode::Char('y') => {
+                                let applied = apply_rename_plan(&mut app);
+
+                                if !applied {
+                                    app.status = "Fix the problems first, or press e to edit again".to_string();
+                                }
+                            }
+
+                            KeyCode::Char('e') => {
+                                if let Some(plan) = app.rename_plan.take() {
+                                    edit_rename_plan(&mut app, &mut terminal, &input_paused, plan.sources, plan.names);
+                                }
+                            }
+
+                            KeyCode::Esc | KeyCode::Char('n') => {
+                                app.rename_plan = None;
+                                app.active_menu_item = AppInfo::MenuItem::Home;
+                                app.status = "Bulk rename cancelled".to_string();
+                            }
+
+                            _ => {}
+                        }
+                    }
+                    AppInfo::InputMode::Normal => {
+                        let action = match resolve_key(&mut app, &event) {
+                            Some(data) => data,
+                            None => continue,
+                        };
+
+                        if run_action(&mut app, action, &mut terminal, &input_paused, &tick_rate) {
+                            disable_raw_mode()?;
+                            terminal.show_cursor()?;
+                            break;
+                        }
+                    }
+                    AppInfo::InputMode::Typing if app.active_menu_item == AppInfo::MenuItem::Search && matches!(app.input_type, AppInfo::InputType::Grep) => {
+                        let found = app.grep.results.lock().unwrap_or_else(|e| e.into_inner()).len();
+
+                        match event.code {
+                            // The first Enter searches, and once the results are in the next one opens the selected line
+                            KeyCode::Enter if app.input != app.grep.pattern || found == 0 => start_grep(&mut app),
+                            KeyCode::Enter => {
+                                let selected = app.search_list_state.selected().and_then(|i| {
+                                    let results = app.grep.results.lock().unwrap_or_else(|e| e.into_inner());
+                                    results.get(i).map(|result| (result.path.clone(), result.line))
+                                });
+
+                                if let Some((path, line)) = selected {
+                                    if let Err(e) = run_editor(&mut terminal, &input_paused, &[format!("+{}", line), path]) {
+                                        app.status = format!("Could not open the editor: {}", e);
+                                    }
+                                }
+                            }
+                            KeyCode::Esc => close_search(&mut app),
+                            KeyCode::Tab => {
+                                app.input_type = AppInfo::InputType::Searching;
+                                update_search(&mut app);
+                            }
+                            KeyCode::Char('x') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                                app.grep.regex = !app.grep.regex;
+                                // Search again on the next Enter
+                                app.grep.pattern.clear();
+                            }
+                            KeyCode::Up => step_selection(&mut app.search_list_state, found, false),
+                            KeyCode::Down => step_selection(&mut app.search_list_state, found, true),
+                            KeyCode::Char(c) => app.input.push(c),
+                            KeyCode::Backspace => {
+                                app.input.pop();
+                            }
+                            _ => {}
+                        }
+                    }
+                    AppInfo::InputMode::Typing if app.active_menu_item == AppInfo::MenuItem::Search => {
+                        match event.code {
+                            KeyCode::Enter => {
+                                // Go to the folder holding the result, with the cursor on it
+                                let selected = app.search_list_state.selected().and_then(|i| {
+                                    app.search_results.lock().unwrap_or_else(|e| e.into_inner()).get(i).map(|result| result.path.clone())
+                                });
+                                if let Some(path) = selected {
+
+                                    if let Ok(Some(parent)) = filesystem::util::move_up_in_path(&path) {
+                                        app.change_directory(&parent);
+                                        app.selected_file = path;
+                                    }
+                                }
+                                close_search(&mut app);
+                            }
+                            KeyCode::Esc => close_search(&mut app),
+                            KeyCode::Tab => {
+                                app.input_type = AppInfo::InputType::Grep;
+                                app.search_list_state.select(Some(0));
+                            }
+                            KeyCode::Up => {
+                                let found = app.search_results.lock().unwrap_or_else(|e| e.into_inner()).len();
+                                step_selection(&mut app.search_list_state, found, false);
+                            }
+                            KeyCode::Down => {
+                                let found = app.search_results.lock().unwrap_or_else(|e| e.into_inner()).len();
+                                step_selection(&mut app.search_list_state, found, true);
+                            }
+                            KeyCode::Char(c) => {
+                                app.input.push(c);
+                                update_search(&mut app);
+                            }
+                            KeyCode::Backspace => {
+                                app.input.pop();
+                                update_search(&mut app);
                             }
+                            _ => {}
+                        }
+                    }
+                    AppInfo::InputMode::Typing if app.active_menu_item == AppInfo::MenuItem::Palette => {
+                        let found = palette::matches(&app.input);
+
+                        match event.code {
+                            KeyCode::Enter => {
+                                let selected = app.palette_list_state.selected().and_then(|i| found.get(i)).map(|(action, _)| *action);
+                                let input = std::mem::take(&mut app.input);
+                                close_palette(&mut app);
 
-                            KeyCode::Char('v') => {
-                                let _ = match filesystem::explorer::paste_file(&app.current_directory){
-                                    Ok(data) => data,
-                                    Err(e) => panic!("Error could not paste file, Error: {}", e),
+                                // A command typed out wins over the list, which only matches action names
+                                let action = match palette::parse(&input) {
+                                    Ok(Some(palette::Command::Action(action))) => Some(action),
+                                    Ok(Some(command)) => {
+                                        run_command(&mut app, command);
+                                        None
+                                    },
+                                    Ok(None) if selected.is_none() => {
+                                        app.status = format!("No command matches {}", input);
+                                        None
+                                    },
+                                    Ok(None) => selected,
+                                    Err(e) => {
+                                        app.status = format!("Could not run {}: {}", input, e);
+                                        None
+                                    },
                                 };
+
+                                if action.is_some_and(|action| run_action(&mut app, action, &mut terminal, &input_paused, &tick_rate)) {
+                                    disable_raw_mode()?;
+                                    terminal.show_cursor()?;
+                                    break;
+                                }
+                            }
+                            KeyCode::Esc => close_palette(&mut app),
+                            KeyCode::Up => step_selection(&mut app.palette_list_state, found.len(), false),
+                            KeyCode::Down => step_selection(&mut app.palette_list_state, found.len(), true),
+                            KeyCode::Char('p') if event.modifiers.contains(KeyModifiers::CONTROL) => step_selection(&mut app.palette_list_state, found.len(), false),
+                            KeyCode::Char('n') if event.modifiers.contains(KeyModifiers::CONTROL) => step_selection(&mut app.palette_list_state, found.len(), true),
+                            KeyCode::Char(c) => {
+                                app.input.push(c);
+                                app.palette_list_state.select(Some(0));
                             }
-                    
                             KeyCode::Backspace => {
-                                if app.current_directory != "C:/".to_string(){
-                                    let mut temp = app.current_directory.clone();
-                                    app.current_directory.clear();
-                    
-                                    temp  = match filesystem::util::move_up_in_path(&temp){ 
-                                        Ok(data) => data.unwrap(),
-                                        Err(e) => panic!("error when moving up a directory"),
-                                    };
-                                    
-                                    app.current_directory.push_str(&temp);
+                                app.input.pop();
+                                app.palette_list_state.select(Some(0));
+                            }
+                            _ => {}
+                        }
+                    }
+                    AppInfo::InputMode::Typing if app.active_menu_item == AppInfo::MenuItem::PatternRename => {
+                        let sources = app.rename_plan.as_ref().map(|plan| plan.sources.len()).unwrap_or(0);
+                        let field = match app.pattern_field {
+                            AppInfo::PatternField::Find => &mut app.rename_pattern.find,
+                            AppInfo::PatternField::Replace => &mut app.rename_pattern.replace,
+                        };
+
+                        match event.code {
+                            KeyCode::Enter => {
+                                if apply_rename_plan(&mut app) {
+                                    app.input_mode = AppInfo::InputMode::Normal;
+                                } else {
+                                    app.status = "Nothing to rename, or the preview shows a problem".to_string();
                                 }
-                                
+                                continue;
+                            }
+                            KeyCode::Esc => {
+                                app.rename_plan = None;
+                                app.input_mode = AppInfo::InputMode::Normal;
+                                app.active_menu_item = AppInfo::MenuItem::Home;
+                                continue;
+                            }
+                            KeyCode::Up => {
+                                step_selection(&mut app.rename_list_state, sources, false);
+                                continue;
+                            }
+                            KeyCode::Down => {
+                                step_selection(&mut app.rename_list_state, sources, true);
+                                continue;
                             }
-                    
-                            KeyCode::Enter =>{
-                                //panic!("Switching directory to /{}/", selected_file);
-                                filesystem::util::update_current_directory(&app.selected_file, &mut app.current_directory);
+                            KeyCode::Tab | KeyCode::BackTab => {
+                                app.pattern_field = match app.pattern_field {
+                                    AppInfo::PatternField::Find => AppInfo::PatternField::Replace,
+                                    AppInfo::PatternField::Replace => AppInfo::PatternField::Find,
+                                };
+                                continue;
+                            }
+                            KeyCode::Char('x') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                                app.rename_pattern.regex = !app.rename_pattern.regex;
+                            }
+                            KeyCode::Char('t') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                                app.rename_pattern.case = app.rename_pattern.case.next();
+                            }
+                            KeyCode::Char(c) => {
+                                field.push(c);
+                            }
+                            KeyCode::Backspace => {
+                                field.pop();
+                            }
+                            _ => continue,
+                        }
+
+                        // Refresh the preview on every change to the pattern
+                        if let Some(plan) = app.rename_plan.take() {
+                            app.rename_plan = Some(filesystem::rename::plan_pattern(&plan.sources, &app.rename_pattern));
+                        }
+                    }
+                    AppInfo::InputMode::Typing if matches!(app.input_type, AppInfo::InputType::SetBookmark | AppInfo::InputType::JumpToBookmark) => {
+                        let set = matches!(app.input_type, AppInfo::InputType::SetBookmark);
+
+                        // A mark is a single key, so it doesn't wait for Enter. Anything that isn't a char gives up
+                        app.input_mode = AppInfo::InputMode::Normal;
+                        app.input_type = AppInfo::InputType::None;
+                        app.active_menu_item = AppInfo::MenuItem::Home;
+
+                        match event.code {
+                            KeyCode::Char(mark) if set => {
+                                let directory = app.current_directory.clone();
+                                filesystem::bookmarks::set(&mut app.bookmarks, mark, &directory);
+                                save_bookmarks(&mut app, format!("Bookmarked {} as {}", directory, mark));
                             }
-                    
+                            KeyCode::Char(mark) => jump_to_bookmark(&mut app, mark),
                             _ => {}
                         }
                     }
                     AppInfo::InputMode::Typing => {
                         match event.code {
                             KeyCode::Enter => {
-                                app.message = (app.input.drain(..).collect());
+                                app.message = app.input.drain(..).collect();
 
                                 app.input_mode = AppInfo::InputMode::Normal;
 
@@ -206,21 +696,106 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     AppInfo::InputType::None => {
                                         app.active_menu_item = AppInfo::MenuItem::Home;
                                     },
-                                    AppInfo::InputType::Searching => {
-                                        app.active_menu_item = AppInfo::MenuItem::Search;
-                                        app.input_type = AppInfo::InputType::None;
+                                    AppInfo::InputType::Searching | AppInfo::InputType::Grep | AppInfo::InputType::Command => {},
+                                    AppInfo::InputType::SetBookmark | AppInfo::InputType::JumpToBookmark => {},
+                                    AppInfo::InputType::Jump => {
+                                        let query = app.message.clone();
+                                        jump(&mut app, &query);
+                                    },
+                                    AppInfo::InputType::RenameBookmark => {
+                                        let index = app.bookmarks_list_state.selected().filter(|i| *i < app.bookmarks.len());
+
+                                        if let Some(index) = index {
+                                            app.bookmarks[index].name = app.message.clone();
+                                            let message = format!("Renamed bookmark {}", app.bookmarks[index].mark);
+                                            save_bookmarks(&mut app, message);
+                                        }
                                     },
                                     AppInfo::InputType::RenameFile => {
-                                        let _ = filesystem::explorer::rename_file(&app.selected_file,&app.current_directory, &app.message);
+                                        // A bad name leaves the entry queued, so the prompt opens on it again
+                                        if app.message.is_empty() {
+                                            app.status = "A name can't be empty".to_string();
+                                        } else if !filesystem::rename::is_valid_name(&app.message) {
+                                            app.status = format!("{} is not a valid file name", app.message);
+                                        } else if !app.rename_queue.is_empty() {
+                                            let original = app.rename_queue.remove(0);
+                                            app.operations.pending.push_back(filesystem::conflict::PendingOperation {
+                                                operation: filesystem::conflict::Operation::Rename,
+                                                destination: std::path::Path::new(&original).with_file_name(&app.message),
+                                                source: original,
+                                                overwrite: false,
+                                            });
+
+                                            if app.rename_queue.is_empty() {
+                                                app.marked.clear();
+                                            }
+                                        }
+                                    },
+                                    AppInfo::InputType::ConflictRename => {
+                                        let name = app.message.clone();
+                                        resolve_conflict(&mut app, filesystem::conflict::Resolution::Rename(name));
                                     },
                                     AppInfo::InputType::MakeFile => {
-                                        let _ = filesystem::explorer::make_file(&app.current_directory,&app.message);
+                                        app.status = match filesystem::explorer::make_file(&app.current_directory, &app.message) {
+                                            Ok(path) => format!("Created {}", path.display()),
+                                            Err(e) => format!("Could not create {}: {}", app.message, e),
+                                        };
+                                        refresh_entries(&mut app);
+                                    },
+                                    AppInfo::InputType::MakeSymlink | AppInfo::InputType::MakeHardLink => {
+                                        let hard = matches!(app.input_type, AppInfo::InputType::MakeHardLink);
+                                        let path = std::path::Path::new(&app.current_directory).join(&app.message);
+
+                                        app.status = match filesystem::explorer::make_link(std::path::Path::new(&app.selected_file), &path, hard) {
+                                            Ok(_) => format!("Linked {} to {}", path.display(), app.selected_file),
+                                            Err(e) => format!("Could not link {}: {}", app.message, e),
+                                        };
+                                        refresh_entries(&mut app);
+                                    },
+                                    AppInfo::InputType::DeletePermanently => {
+                                        if app.message.eq_ignore_ascii_case("y") {
+                                            let targets = app.targets();
+
+                                            app.jobs.spawn(format!("Delete {} item(s) permanently", targets.len()), move |progress| {
+                                                measure_all(&targets, progress);
+
+                                                for file in &targets {
+                                                    filesystem::explorer::delete_permanently(file, progress)?;
+                                                }
+                                                Ok(())
+                                            });
+                                            app.marked.clear();
+                                        }
+                                    },
+                                    AppInfo::InputType::SelectGlob => {
+                                        match glob::Pattern::new(&app.message) {
+                                            Ok(pattern) => {
+                                                for file in app.entry_paths() {
+                                                    if pattern.matches(&filesystem::util::strip_directory(&file)) {
+                                                        app.marked.insert(file);
+                                                    }
+                                                }
+                                            },
+                                            Err(e) => app.status = format!("Invalid pattern: {}", e),
+                                        }
+                                    },
+                                    AppInfo::InputType::EmptyTrash => {
+                                        if app.message.eq_ignore_ascii_case("y") {
+                                            app.jobs.spawn("Empty the trash".to_string(), filesystem::trash::empty_trash);
+                                            app.trash_entries.clear();
+                                        }
                                     },
                                 }
 
+                                app.active_menu_item = match app.input_type {
+                                    AppInfo::InputType::EmptyTrash => AppInfo::MenuItem::Trash,
+                                    AppInfo::InputType::RenameBookmark => AppInfo::MenuItem::Bookmarks,
+                                    _ => AppInfo::MenuItem::Home,
+                                };
                                 app.input_type = AppInfo::InputType::None;
-                                app.active_menu_item = AppInfo::MenuItem::Home;
 
+                                // Renames run through the same queue as pastes, which may stop on a conflict
+                                process_pending(&mut app);
                             }
                             KeyCode::Char(c) => {
                                 app.input.push(c);
@@ -229,8 +804,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 app.input.pop();
                             }
                             KeyCode::Esc => {
-                                app.active_menu_item = AppInfo::MenuItem::Home;
+                                app.active_menu_item = match app.input_type {
+                                    AppInfo::InputType::EmptyTrash => AppInfo::MenuItem::Trash,
+                                    AppInfo::InputType::ConflictRename => AppInfo::MenuItem::Conflict,
+                                    AppInfo::InputType::RenameBookmark => AppInfo::MenuItem::Bookmarks,
+                                    _ => AppInfo::MenuItem::Home,
+                                };
                                 app.input_mode = AppInfo::InputMode::Normal;
+                                app.input_type = AppInfo::InputType::None;
+                                app.input.clear();
+                                app.rename_queue.clear();
                             }
                             _ => {}
                         }
@@ -238,10 +821,845 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                    
                 }
             }
-            Event::Tick => {}
+            Event::Changed => {
+                changed.store(false, Ordering::Relaxed);
+                refresh_entries(&mut app);
+            }
+            Event::Tick => {
+                let finished = app.jobs.poll();
+
+                if let Some(message) = finished.last() {
+                    app.status = message.clone();
+                    refresh_entries(&mut app);
+
+                    if app.active_menu_item == AppInfo::MenuItem::Trash {
+                        reload_trash(&mut app);
+                    }
+                }
+            }
             // ... other event cases
         }
     }
 
     Ok(())
 }
+
+/// Hands the terminal over to `$VISUAL` or `$EDITOR` until it exits
+fn run_editor(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input_paused: &AtomicBool, args: &[String]) -> Result<(), io::Error> {
+    let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).unwrap_or_else(|_| "vi".to_string());
+    run_in_terminal(terminal, input_paused, &editor, args)
+}
+
+/// Runs a command with the terminal handed over to it until it exits. `command` may include its own arguments
+fn run_in_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input_paused: &AtomicBool, command: &str, args: &[String]) -> Result<(), io::Error> {
+    let mut words = command.split_whitespace();
+    let program = words.next().unwrap_or("vi").to_string();
+
+    // Give the input thread time to finish its current poll so it doesn't swallow the editor's keys
+    input_paused.store(true, Ordering::Relaxed);
+    thread::sleep(Duration::from_millis(250));
+    disable_raw_mode()?;
+    terminal.show_cursor()?;
+
+    let status = Command::new(&program).args(words).args(args).status();
+
+    enable_raw_mode()?;
+    terminal.clear()?;
+    input_paused.store(false, Ordering::Relaxed);
+
+    match status? {
+        status if status.success() => Ok(()),
+        status => Err(io::Error::other(format!("{} exited with {}", program, status))),
+    }
+}
+
+/// Opens a file with the opener configured for its extension, or else the system default
+fn open_with_opener(app: &AppInfo::App, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input_paused: &AtomicBool, file: &str) -> Result<(), io::Error> {
+    let extension = std::path::Path::new(file).extension().map(|extension| extension.to_string_lossy().to_lowercase());
+    let opener = match extension.and_then(|extension| app.config.openers.get(&extension)) {
+        Some(data) => data,
+        None => return filesystem::explorer::open_file(file.to_string()),
+    };
+
+    if opener.terminal() {
+        return run_in_terminal(terminal, input_paused, opener.command(), &[file.to_string()]);
+    }
+
+    let mut words = opener.command().split_whitespace();
+    let program = words.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the opener is empty"))?;
+
+    // Left running on its own, with its output kept off the UI
+    Command::new(program)
+        .args(words)
+        .arg(file)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+
+    Ok(())
+}
+
+/// Reads the config file again and applies everything but the start directory
+fn reload_config(app: &mut AppInfo::App, tick_rate: &AtomicU64) {
+    let mut config = match config::load(&app.config_path, app.config_required) {
+        Ok(data) => data,
+        Err(Error::ParseConfigError(e)) => {
+            app.status = format!("Could not reload {}: {}", app.config_path.display(), e.message());
+            return;
+        },
+        Err(e) => {
+            app.status = format!("Could not reload {}: {}", app.config_path.display(), e);
+            return;
+        },
+    };
+    config.resolve(&app.start_directory);
+
+    let index_changed = config.index != app.config.index;
+    tick_rate.store(config.tick_rate, Ordering::Relaxed);
+    app.sort = config.sort;
+    app.keymap = config.keymap.keymap();
+    app.pending_keys.clear();
+    app.config = config;
+
+    let _ = app.reload_entries();
+    if index_changed {
+        refresh_index(app, false);
+    }
+
+    app.status = format!("Reloaded {}", app.config_path.display());
+}
+
+/// Lets the user edit `names` in their editor and shows the resulting renames for confirmation
+fn edit_rename_plan(app: &mut AppInfo::App, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input_paused: &AtomicBool, sources: Vec<String>, names: Vec<String>) {
+    let edited = filesystem::rename::edit_file_path().and_then(|path| {
+        let edited = filesystem::rename::write_names(&path, &names)
+            .and_then(|_| run_editor(terminal, input_paused, &[path.to_string_lossy().into_owned()]))
+            .and_then(|_| filesystem::rename::read_names(&path));
+
+        filesystem::rename::remove_edit_file(&path);
+        edited
+    });
+
+    let names = match edited {
+        Ok(data) => data,
+        Err(e) => {
+            app.status = format!("Bulk rename failed: {}", e);
+            app.active_menu_item = AppInfo::MenuItem::Home;
+            return;
+        }
+    };
+
+    let plan = filesystem::rename::plan(&sources, &names);
+
+    if plan.problems.is_empty() && plan.changes.is_empty() {
+        app.status = "No names were changed".to_string();
+        app.active_menu_item = AppInfo::MenuItem::Home;
+        return;
+    }
+
+    app.rename_plan = Some(plan);
+    app.rename_list_state.select(Some(0));
+    app.active_menu_item = AppInfo::MenuItem::RenamePreview;
+}
+
+/// Starts the checked renames as one job. Returns false when the plan can't run
+fn apply_rename_plan(app: &mut AppInfo::App) -> bool {
+    match app.rename_plan.take() {
+        Some(plan) if plan.is_valid() => {
+            app.jobs.spawn(format!("Rename {} item(s)", plan.changes.len()), move |progress| {
+                filesystem::rename::apply(&plan, progress)
+            });
+            app.marked.clear();
+            app.active_menu_item = AppInfo::MenuItem::Home;
+            true
+        },
+        plan => {
+            app.rename_plan = plan;
+            false
+        },
+    }
+}
+
+/// Moves the watcher over to the directory now shown and loads its listing
+fn follow_directory(app: &mut AppInfo::App, watcher: Option<&mut notify::RecommendedWatcher>, previous: &str) {
+    use notify::Watcher;
+
+    // The entries are still the previous folder's, so remember where the cursor was in it
+    if let Some(entry) = app.directory_list_state.selected().and_then(|i| app.entries.get(i)) {
+        app.history.selections.insert(previous.to_string(), entry.path.clone());
+    }
+
+    // A search result picks its own entry. Otherwise go back to where the cursor was, or to the folder just left when going up
+    let picked = std::path::Path::new(&app.selected_file).parent() == Some(std::path::Path::new(&app.current_directory));
+    if !picked {
+        app.selected_file = app.history.selections.get(&app.current_directory).cloned().unwrap_or_else(|| previous.to_string());
+    }
+
+    app.directory_list_state.select(Some(0));
+    if let Err(e) = app.reload_entries() {
+        // Stay in the previous folder, without counting the visit or adding it to the history
+        app.status = format!("Could not read {}: {}", app.current_directory, e);
+        app.history.pending = None;
+        app.current_directory = previous.to_string();
+        app.selected_file = app.history.selections.get(previous).cloned().unwrap_or_default();
+        let _ = app.reload_entries();
+        return;
+    }
+
+    app.record_history(previous);
+    app.frecency.visit(&app.current_directory, filesystem::frecency::now());
+    if let Err(e) = filesystem::frecency::save(&app.frecency) {
+        app.status = format!("Could not save the visited folders: {}", e);
+    }
+
+    if let Some(watcher) = watcher {
+        let _ = watcher.unwatch(std::path::Path::new(previous));
+        let _ = watcher.watch(std::path::Path::new(&app.current_directory), notify::RecursiveMode::NonRecursive);
+    }
+}
+
+/// Updates the file index as a job, reading only changed folders unless `rebuild` is set
+fn refresh_index(app: &mut AppInfo::App, rebuild: bool) {
+    let index = Arc::clone(&app.index);
+    let config = app.config.index.clone();
+    let description = if rebuild { "Rebuild the file index" } else { "Update the file index" };
+
+    app.jobs.spawn(description.to_string(), move |progress| {
+        let updated = {
+            let previous = index.read().unwrap_or_else(|e| e.into_inner());
+            filesystem::index::update(&config, &previous, rebuild, progress)?
+        };
+
+        filesystem::index::save(&updated).map_err(io::Error::other)?;
+        *index.write().unwrap_or_else(|e| e.into_inner()) = updated;
+        Ok(())
+    });
+}
+
+/// Does what a key or the command palette asked for in the listing. Returns true when the explorer should quit
+fn run_action(app: &mut AppInfo::App, action: Action, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input_paused: &AtomicBool, tick_rate: &AtomicU64) -> bool {
+    match action {
+        Action::Quit if app.jobs.active() > 0 && !app.quit_pending => {
+            let keys = app.keymap.keys_for(Action::Quit).map(|keys| keys.label()).unwrap_or_default();
+            app.quit_pending = true;
+            app.status = format!("{} job(s) still running. Press {} again to cancel them and quit", app.jobs.active(), keys);
+        }
+
+        Action::Quit => return true,
+
+        Action::Up => {
+            step_selection(&mut app.directory_list_state, app.entries.len(), false);
+        }
+
+        Action::Down => {
+            step_selection(&mut app.directory_list_state, app.entries.len(), true);
+        }
+
+        Action::Top => {
+            app.directory_list_state.select(Some(0));
+        }
+
+        Action::Bottom => {
+            app.directory_list_state.select(Some(app.entries.len().saturating_sub(1)));
+        }
+
+        Action::ToggleMark if !app.selected_file.is_empty() => {
+            let selected = app.selected_file.clone();
+            app.toggle_mark(&selected);
+            step_selection(&mut app.directory_list_state, app.entries.len(), true);
+        }
+
+        Action::MarkAll => {
+            app.marked.extend(app.entry_paths());
+        }
+
+        Action::InvertMarks => {
+            for file in app.entry_paths() {
+                app.toggle_mark(&file);
+            }
+        }
+
+        Action::MarkGlob => {
+            begin_input(app, AppInfo::InputType::SelectGlob);
+        }
+
+        Action::ClearMarks => {
+            app.marked.clear();
+        }
+
+        Action::Open =>{
+            for file in app.targets() {
+                if let Err(e) = open_with_opener(app, terminal, input_paused, &file) {
+                    app.status = format!("Could not open {}: {}", filesystem::util::strip_directory(&file), e);
+                }
+            }
+        }
+
+        Action::ReloadConfig => reload_config(app, tick_rate),
+
+        Action::SortByName => sort_by(app, filesystem::listing::SortKey::Name),
+        Action::SortBySize => sort_by(app, filesystem::listing::SortKey::Size),
+        Action::SortByModified => sort_by(app, filesystem::listing::SortKey::Modified),
+        Action::SortByPermissions => sort_by(app, filesystem::listing::SortKey::Permissions),
+        Action::SortByType => sort_by(app, filesystem::listing::SortKey::Type),
+
+        Action::Palette => {
+            begin_input(app, AppInfo::InputType::Command);
+            app.active_menu_item = AppInfo::MenuItem::Palette;
+            app.palette_list_state.select(Some(0));
+        }
+
+        Action::Search => {
+            begin_input(app, AppInfo::InputType::Searching);
+            app.active_menu_item = AppInfo::MenuItem::Search;
+            update_search(app);
+        }
+
+        Action::FindInFiles => {
+            begin_input(app, AppInfo::InputType::Grep);
+            app.active_menu_item = AppInfo::MenuItem::Search;
+            app.search_list_state.select(Some(0));
+        }
+
+        Action::NewFile => {
+            begin_input(app, AppInfo::InputType::MakeFile);
+        }
+
+        Action::Symlink if !app.selected_file.is_empty() => {
+            begin_input(app, AppInfo::InputType::MakeSymlink);
+        }
+
+        Action::Permissions if !app.selected_file.is_empty() => {
+            let targets = app.targets();
+
+            match std::fs::symlink_metadata(&targets[0]) {
+                Ok(md) => {
+                    let mode = filesystem::permissions::Attributes::of(&md).mode;
+                    let owner = filesystem::permissions::owner_string(&md);
+
+                    app.permissions = Some(AppInfo::PermissionsDialog::new(targets, mode, owner));
+                    app.active_menu_item = AppInfo::MenuItem::Permissions;
+                },
+                Err(e) => app.status = format!("Could not read {}: {}", targets[0], e),
+            }
+        }
+
+        Action::HardLink if !app.selected_file.is_empty() => {
+            begin_input(app, AppInfo::InputType::MakeHardLink);
+        }
+
+        // Undoing can move whole folders back, so it runs as a job like the change it reverses
+        Action::Undo => match filesystem::journal::next_undo() {
+            Some(description) => {
+                app.jobs.spawn(format!("Undo {}", description), |progress| filesystem::journal::undo(progress).map(|_| ()));
+            }
+            None => app.status = "Nothing to undo".to_string(),
+        },
+
+        Action::Redo => match filesystem::journal::next_redo() {
+            Some(description) => {
+                app.jobs.spawn(format!("Redo {}", description), |progress| filesystem::journal::redo(progress).map(|_| ()));
+            }
+            None => app.status = "Nothing to redo".to_string(),
+        },
+
+        Action::Rename => {
+            app.rename_queue = app.targets();
+            next_rename(app);
+        }
+
+        Action::BulkRename => {
+            let sources = match app.marked.is_empty() {
+                true => app.entry_paths(),
+                false => app.marked.iter().cloned().collect(),
+            };
+            let names = sources.iter().map(|source| filesystem::util::strip_directory(source)).collect();
+
+            edit_rename_plan(app, terminal, input_paused, sources, names);
+        }
+
+        Action::PatternRename => {
+            let sources = match app.marked.is_empty() {
+                true => app.entry_paths(),
+                false => app.marked.iter().cloned().collect(),
+            };
+
+            app.rename_plan = Some(filesystem::rename::plan_pattern(&sources, &app.rename_pattern));
+            app.rename_list_state.select(Some(0));
+            app.pattern_field = AppInfo::PatternField::Find;
+            app.input_mode = AppInfo::InputMode::Typing;
+            app.active_menu_item = AppInfo::MenuItem::PatternRename;
+        }
+
+        Action::Trash => {
+            let targets = app.targets();
+
+            app.jobs.spawn(format!("Move {} item(s) to the trash", targets.len()), move |progress| {
+                measure_all(&targets, progress);
+
+                for file in &targets {
+                    filesystem::explorer::delete_file(file, progress)?;
+                }
+                Ok(())
+            });
+            app.marked.clear();
+        }
+
+        Action::Reindex => {
+            refresh_index(app, true);
+        }
+
+        Action::ShowJobs => {
+            app.active_menu_item = AppInfo::MenuItem::Jobs;
+        }
+
+        Action::DeletePermanently => {
+            begin_input(app, AppInfo::InputType::DeletePermanently);
+        }
+
+        Action::ShowTrash => {
+            app.active_menu_item = AppInfo::MenuItem::Trash;
+            reload_trash(app);
+        }
+
+        Action::Copy => {
+            app.clipboard.set(app.targets(), AppInfo::ClipboardMode::Copy);
+            app.marked.clear();
+        }
+
+        Action::Cut => {
+            app.clipboard.set(app.targets(), AppInfo::ClipboardMode::Cut);
+            app.marked.clear();
+        }
+
+        Action::Paste if !app.clipboard.is_empty() => {
+            let cut = app.clipboard.mode == AppInfo::ClipboardMode::Cut;
+
+            app.operations.pending.extend(filesystem::conflict::plan_paste(&app.clipboard.paths, cut, &app.current_directory));
+            process_pending(app);
+        }
+
+        Action::Parent => {
+            // The root has no parent to go to
+            if let Ok(Some(parent)) = filesystem::util::move_up_in_path(&app.current_directory) {
+                app.change_directory(&parent);
+            }
+        }
+
+        Action::Back if !app.step_history(false) => {
+            app.status = "Nothing to go back to".to_string();
+        }
+
+        Action::Forward if !app.step_history(true) => {
+            app.status = "Nothing to go forward to".to_string();
+        }
+
+        Action::SetBookmark => {
+            begin_input(app, AppInfo::InputType::SetBookmark);
+        }
+
+        Action::JumpToBookmark => {
+            begin_input(app, AppInfo::InputType::JumpToBookmark);
+        }
+
+        Action::Jump => {
+            begin_input(app, AppInfo::InputType::Jump);
+        }
+
+        Action::ShowBookmarks => {
+            app.active_menu_item = AppInfo::MenuItem::Bookmarks;
+            let selected = app.bookmarks_list_state.selected().unwrap_or(0);
+            app.bookmarks_list_state.select(Some(selected.min(app.bookmarks.len().saturating_sub(1))));
+        }
+
+        Action::Enter if std::path::Path::new(&app.selected_file).is_dir() =>{
+            //panic!("Switching directory to /{}/", selected_file);
+            let selected = app.selected_file.clone();
+            app.change_directory(&selected);
+        }
+
+        _ => {}
+    }
+
+    false
+}
+
+fn close_palette(app: &mut AppInfo::App) {
+    app.input.clear();
+    app.input_mode = AppInfo::InputMode::Normal;
+    app.input_type = AppInfo::InputType::None;
+    app.active_menu_item = AppInfo::MenuItem::Home;
+}
+
+/// Runs a palette command that takes arguments. Actions go through `run_action` instead
+fn run_command(app: &mut AppInfo::App, command: palette::Command) {
+    match command {
+        palette::Command::Action(_) => {},
+        palette::Command::Mkdir(name) => {
+            // A trailing slash is how make_file knows to make a folder
+            let folder = format!("{}/", name.trim_end_matches('/'));
+
+            app.status = match filesystem::explorer::make_file(&app.current_directory, &folder) {
+                Ok(path) => format!("Created {}", path.display()),
+                Err(e) => format!("Could not create {}: {}", name, e),
+            };
+            refresh_entries(app);
+        },
+        palette::Command::Cd(folder) => {
+            let path = std::path::Path::new(&app.current_directory).join(filesystem::util::expand_home(&folder));
+
+            match path.canonicalize() {
+                Ok(path) if path.is_dir() => app.change_directory(&path.to_string_lossy()),
+                Ok(_) => app.status = format!("Not a folder: {}", folder),
+                Err(e) => app.status = format!("Could not go to {}: {}", folder, e),
+            }
+        },
+        palette::Command::Sort(sort) => {
+            app.sort = sort;
+            app.sort_entries();
+        },
+        palette::Command::Jump(query) => jump(app, &query),
+        palette::Command::Import(source) => {
+            app.status = match app.frecency.import(source) {
+                Ok(amount) => match filesystem::frecency::save(&app.frecency) {
+                    Ok(_) => format!("Imported {} folder(s)", amount),
+                    Err(e) => format!("Could not save the visited folders: {}", e),
+                },
+                Err(e) => format!("Could not import: {}", e),
+            };
+        },
+    }
+}
+
+/// Goes to the most frecent folder matching `query`
+fn jump(app: &mut AppInfo::App, query: &str) {
+    let best = app.frecency.best(query, &app.current_directory, filesystem::frecency::now()).map(str::to_string);
+
+    match best {
+        Some(directory) => app.change_directory(&directory),
+        None => app.status = format!("No visited folder matches {}", query),
+    }
+}
+
+fn jump_to_bookmark(app: &mut AppInfo::App, mark: char) {
+    let path = match app.bookmarks.iter().find(|bookmark| bookmark.mark == mark) {
+        Some(bookmark) => bookmark.path.clone(),
+        None => {
+            app.status = format!("No bookmark {}", mark);
+            return;
+        },
+    };
+
+    match std::path::Path::new(&path).is_dir() {
+        true => app.change_directory(&path),
+        false => app.status = format!("Bookmark {} is {}, which isn't a folder any more", mark, path),
+    }
+}
+
+/// Writes the bookmarks out after a change, showing `message` if that worked
+fn save_bookmarks(app: &mut AppInfo::App, message: String) {
+    app.status = match filesystem::bookmarks::save(&app.bookmarks) {
+        Ok(_) => message,
+        Err(e) => format!("Could not save the bookmarks: {}", e),
+    };
+}
+
+/// Adds a key to the ones pressed so far, returning the action once they make up a whole binding
+fn resolve_key(app: &mut AppInfo::App, event: &KeyEvent) -> Option<Action> {
+    // Esc gives up on a half typed sequence instead of doing anything
+    if event.code == KeyCode::Esc && !app.pending_keys.is_empty() {
+        app.pending_keys.clear();
+        return None;
+    }
+
+    app.pending_keys.push(Key::of(event));
+
+    match app.keymap.lookup(&app.pending_keys) {
+        Lookup::Action(action) => {
+            app.pending_keys.clear();
+            Some(action)
+        },
+        Lookup::Pending => None,
+        Lookup::None => {
+            app.pending_keys.clear();
+            None
+        },
+    }
+}
+
+fn sort_by(app: &mut AppInfo::App, key: filesystem::listing::SortKey) {
+    app.sort.select(key);
+    app.sort_entries();
+}
+
+/// Re-runs the search for what's been typed so far
+fn update_search(app: &mut AppInfo::App) {
+    if let Some(progress) = app.search_progress.take() {
+        progress.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    // Keep showing the last results while the query is half typed
+    let query = match filesystem::query::parse(&app.input) {
+        Ok(query) => query,
+        Err(e) => {
+            app.search_error = Some(e.to_string());
+            return;
+        },
+    };
+    app.search_error = None;
+    app.search_list_state.select(Some(0));
+
+    // Scoring the whole index takes a while on big trees, so it runs in the background like a content search
+    let index = Arc::clone(&app.index);
+    let results = Arc::clone(&app.search_results);
+    app.search_progress = Some(app.jobs.spawn_untracked(move |progress| {
+        let found = {
+            let index = index.read().unwrap_or_else(|e| e.into_inner());
+            filesystem::index::search(&index, &query, progress)?
+        };
+
+        // Checked again under the lock, so a search replaced while it finished can't overwrite the newer one
+        let mut results = results.lock().unwrap_or_else(|e| e.into_inner());
+        progress.checkpoint()?;
+        *results = found;
+        Ok(())
+    }));
+}
+
+/// Starts searching file contents under the current folder for what's been typed, replacing any search still running
+fn start_grep(app: &mut AppInfo::App) {
+    if let Some(job) = app.grep.job.take() {
+        app.jobs.cancel(job);
+    }
+
+    // A fresh list, so a cancelled search can't add to the new one
+    app.grep.results = Arc::new(Mutex::new(Vec::new()));
+    app.grep.pattern = app.input.clone();
+    app.grep.root = app.current_directory.clone();
+    app.grep.error = None;
+    app.search_list_state.select(Some(0));
+
+    if app.input.is_empty() {
+        return;
+    }
+
+    match filesystem::grep::pattern(&app.input, app.grep.regex) {
+        Ok(pattern) => {
+            let results = Arc::clone(&app.grep.results);
+            let root = app.grep.root.clone();
+
+            app.grep.job = Some(app.jobs.spawn(format!("Search file contents for {}", app.input), move |progress| {
+                filesystem::grep::search(&root, &pattern, &results, progress)
+            }));
+        },
+        Err(e) => app.grep.error = Some(e.to_string()),
+    }
+}
+
+fn close_search(app: &mut AppInfo::App) {
+    if let Some(job) = app.grep.job.take() {
+        app.jobs.cancel(job);
+    }
+    app.grep.pattern.clear();
+    app.grep.results = Arc::new(Mutex::new(Vec::new()));
+    app.grep.error = None;
+
+    if let Some(progress) = app.search_progress.take() {
+        progress.cancelled.store(true, Ordering::Relaxed);
+    }
+    app.input.clear();
+    app.search_results.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    app.search_error = None;
+    app.input_mode = AppInfo::InputMode::Normal;
+    app.input_type = AppInfo::InputType::None;
+    app.active_menu_item = AppInfo::MenuItem::Home;
+}
+
+fn begin_input(app: &mut AppInfo::App, input_type: AppInfo::InputType) {
+    app.active_menu_item = AppInfo::MenuItem::Text;
+    app.input_mode = AppInfo::InputMode::Typing;
+    app.input_type = input_type;
+}
+
+/// Re-reads the listing after the app changed something itself, so it shows even when the watcher isn't running
+fn refresh_entries(app: &mut AppInfo::App) {
+    if let Err(e) = app.reload_entries() {
+        app.status = format!("Could not read {}: {}", app.current_directory, e);
+    }
+}
+
+/// Opens the rename prompt for the next queued entry, pre-filled with its current name
+fn next_rename(app: &mut AppInfo::App) {
+    if let Some(next) = app.rename_queue.first() {
+        app.input = filesystem::util::strip_directory(next);
+        begin_input(app, AppInfo::InputType::RenameFile);
+    }
+}
+
+/// Resolves queued operations until one needs the user to pick what to do, then runs the rest as a job
+fn process_pending(app: &mut AppInfo::App) {
+    while let Some(mut operation) = app.operations.pending.pop_front() {
+        let planned = app.operations.planned();
+
+        if filesystem::conflict::has_conflict(&operation, &planned) {
+            let resolution = match filesystem::conflict::automatic_resolution(&operation).or(app.operations.resolution.clone()) {
+                Some(resolution) => resolution,
+                None => {
+                    app.operations.pending.push_front(operation);
+                    app.active_menu_item = AppInfo::MenuItem::Conflict;
+                    return;
+                }
+            };
+
+            match filesystem::conflict::resolve(&mut operation, &resolution, &planned) {
+                Ok(true) => {
+                    // A new name can clash again, so check it once more before running
+                    app.operations.pending.push_front(operation);
+                    continue;
+                },
+                Ok(false) => {
+                    app.operations.skipped += 1;
+                    continue;
+                },
+                Err(e) => {
+                    app.status = format!("Could not resolve {}: {}", filesystem::util::strip_directory(&operation.source), e);
+                    app.operations.failed += 1;
+                    continue;
+                },
+            }
+        }
+
+        app.operations.ready.push(operation);
+    }
+
+    let ready = std::mem::take(&mut app.operations.ready);
+
+    // Cut entries are on their way now, so they can only be pasted once. A batch cancelled at a conflict never gets here
+    if app.clipboard.mode == AppInfo::ClipboardMode::Cut && ready.iter().any(|operation| operation.operation == filesystem::conflict::Operation::Move) {
+        app.clipboard.clear();
+    }
+
+    if let Some(first) = ready.first() {
+        let verb = match first.operation {
+            filesystem::conflict::Operation::Copy => "Copy",
+            filesystem::conflict::Operation::Move => "Move",
+            filesystem::conflict::Operation::Rename => "Rename",
+        };
+        let destination = first.destination.parent().map(|parent| parent.to_string_lossy().into_owned()).unwrap_or_default();
+
+        let description = match ready.as_slice() {
+            [single] if single.operation == filesystem::conflict::Operation::Rename => format!(
+                "Rename {} to {}",
+                filesystem::util::strip_directory(&single.source),
+                single.destination.file_name().unwrap_or_default().to_string_lossy(),
+            ),
+            _ => format!("{} {} item(s) to {}", verb, ready.len(), destination),
+        };
+
+        app.jobs.spawn(description, move |progress| {
+            filesystem::conflict::run_all(&ready, progress)
+        });
+    }
+
+    // Leave the last error on screen rather than hiding it behind a summary
+    if app.operations.failed == 0 && app.operations.skipped > 0 {
+        app.status = format!("Skipped {} item(s)", app.operations.skipped);
+    }
+    finish_pending(app);
+}
+
+fn finish_pending(app: &mut AppInfo::App) {
+    app.operations = AppInfo::OperationQueue::default();
+
+    if app.active_menu_item == AppInfo::MenuItem::Conflict {
+        app.active_menu_item = AppInfo::MenuItem::Home;
+    }
+
+    // Keep prompting while a batch rename still has entries left
+    next_rename(app);
+}
+
+/// Resolves the conflict at the front of the queue and carries on with the rest
+fn resolve_conflict(app: &mut AppInfo::App, resolution: filesystem::conflict::Resolution) {
+    if app.operations.apply_to_all && !matches!(resolution, filesystem::conflict::Resolution::Rename(_)) {
+        app.operations.resolution = Some(resolution.clone());
+    }
+
+    if let Some(mut operation) = app.operations.pending.pop_front() {
+        let planned = app.operations.planned();
+
+        match filesystem::conflict::resolve(&mut operation, &resolution, &planned) {
+            Ok(true) => app.operations.pending.push_front(operation),
+            Ok(false) => app.operations.skipped += 1,
+            Err(e) => {
+                app.status = format!("Could not resolve {}: {}", filesystem::util::strip_directory(&operation.source), e);
+                app.operations.failed += 1;
+            },
+        }
+    }
+
+    process_pending(app);
+}
+
+fn measure_all(paths: &[String], progress: &jobs::JobProgress) {
+    for path in paths {
+        let (bytes, files) = filesystem::util::measure(std::path::Path::new(path));
+        progress.add_total(bytes, files);
+    }
+}
+
+/// Lets `step_selection` move through lists and tables alike
+trait Selection {
+    fn selected(&self) -> Option<usize>;
+    fn select(&mut self, index: Option<usize>);
+}
+
+impl Selection for ListState {
+    fn selected(&self) -> Option<usize> {
+        ListState::selected(self)
+    }
+
+    fn select(&mut self, index: Option<usize>) {
+        ListState::select(self, index)
+    }
+}
+
+impl Selection for TableState {
+    fn selected(&self) -> Option<usize> {
+        TableState::selected(self)
+    }
+
+    fn select(&mut self, index: Option<usize>) {
+        TableState::select(self, index)
+    }
+}
+
+fn step_selection(state: &mut impl Selection, len: usize, forward: bool) {
+    if let Some(selected) = state.selected() {
+        if len == 0 {
+            state.select(Some(0));
+        } else if forward {
+            state.select(Some(if selected + 1 >= len { 0 } else { selected + 1 }));
+        } else {
+            state.select(Some(if selected > 0 { selected - 1 } else { len - 1 }));
+        }
+    }
+}
+
+fn reload_trash(app: &mut AppInfo::App) {
+    app.trash_entries = match filesystem::trash::list_trash(){
+        Ok((entries, 0)) => entries,
+        Ok((entries, unreadable)) => {
+            app.status = format!("Skipped {} trash record(s) that could not be read", unreadable);
+            entries
+        }
+        Err(e) => {
+            app.status = format!("Could not read the trash: {}", e);
+            Vec::new()
+        }
+    };
+
+    let selected = app.trash_list_state.selected().unwrap_or(0);
+    app.trash_list_state.select(Some(selected.min(app.trash_entries.len().saturating_sub(1))));
+}
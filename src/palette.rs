@@ -0,0 +1,89 @@
+use std::io::{Error, ErrorKind};
+
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::Deserialize;
+
+use crate::filesystem::frecency::Source;
+use crate::filesystem::listing::{Sort, SortKey};
+use crate::fuzzy;
+use crate::keymap::Action;
+
+/// Something typed into the command palette that runs without picking from the list.
+pub enum Command {
+    /// Any action by its config name, e.g. `reindex`
+    Action(Action),
+    /// `mkdir <name>`, which can make parent folders too
+    Mkdir(String),
+    /// `cd <folder>`, relative to the current folder or starting with `~`
+    Cd(String),
+    /// `sort <column> [asc|desc]`
+    Sort(Sort),
+    /// `z <words>`, to the most frecent folder matching them
+    Jump(String),
+    /// `import zoxide` or `import autojump`
+    Import(Source),
+}
+
+/// The actions whose label fuzzy matches `query`, best first, with the matched char positions.
+pub fn matches(query: &str) -> Vec<(Action, Vec<usize>)> {
+    let mut found: Vec<(i64, Action, Vec<usize>)> = Action::HINTS
+        .iter()
+        .filter(|&&action| action != Action::Palette)
+        .filter_map(|&action| fuzzy::score(query, action.label()).map(|(score, positions)| (score, action, positions)))
+        .collect();
+
+    // Stable, so equal scores keep the bottom bar's order
+    found.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+    found.into_iter().map(|(_, action, positions)| (action, positions)).collect()
+}
+
+/// Reads what was typed as a command. Returns `None` when it isn't one, so it picks from the list instead.
+pub fn parse(text: &str) -> Result<Option<Command>, Error> {
+    let text = text.trim().trim_start_matches(':');
+    let (name, argument) = match text.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (text, ""),
+    };
+
+    let command = match name {
+        "mkdir" if !argument.is_empty() => Command::Mkdir(argument.to_string()),
+        "cd" if !argument.is_empty() => Command::Cd(argument.to_string()),
+        "sort" if !argument.is_empty() => Command::Sort(parse_sort(argument)?),
+        "z" if !argument.is_empty() => Command::Jump(argument.to_string()),
+        "import" if !argument.is_empty() => Command::Import(match argument {
+            "zoxide" => Source::Zoxide,
+            "autojump" => Source::Autojump,
+            _ => return Err(invalid(format!("can import from zoxide or autojump, not {}", argument))),
+        }),
+        "mkdir" | "cd" | "sort" | "z" | "import" => return Ok(None),
+        _ => match Action::deserialize(StrDeserializer::<ValueError>::new(name)) {
+            Ok(_) if !argument.is_empty() => return Err(invalid(format!("{} doesn't take anything after it", name))),
+            Ok(action) => Command::Action(action),
+            Err(_) => return Ok(None),
+        },
+    };
+
+    Ok(Some(command))
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+fn parse_sort(argument: &str) -> Result<Sort, Error> {
+    let mut words = argument.split_whitespace();
+    let column = words.next().unwrap_or_default();
+
+    let key = SortKey::deserialize(StrDeserializer::<ValueError>::new(&column.to_lowercase()))
+        .map_err(|_| invalid(format!("can't sort by {}, only name, size, modified, permissions or type", column)))?;
+    let descending = match words.next() {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(word) => return Err(invalid(format!("expected asc or desc, not {}", word))),
+    };
+
+    match words.next() {
+        Some(word) => Err(invalid(format!("unexpected {}", word))),
+        None => Ok(Sort { key, descending }),
+    }
+}